@@ -0,0 +1,25 @@
+-- Outbox bookkeeping: keep the original payload so deliveries can be
+-- rendered again after a restart, and track retries per channel.
+ALTER TABLE notification_events ADD COLUMN payload TEXT;
+
+ALTER TABLE service_notifications ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE service_notifications ADD COLUMN last_attempt_at TIMESTAMP;
+ALTER TABLE service_notifications ADD COLUMN next_attempt_at TIMESTAMP; -- NULL means due now
+
+CREATE INDEX idx_service_notifications_next_attempt_at ON service_notifications(next_attempt_at);
+
+-- 'pending' rows have not been tried yet, 'failed' rows are waiting for a retry,
+-- 'dead' rows ran out of attempts
+DROP VIEW v_pending_notifications;
+CREATE VIEW v_pending_notifications AS
+SELECT
+    sn.id as notification_id,
+    sn.event_id,
+    sn.service_type,
+    sn.attempts,
+    sn.next_attempt_at,
+    ne.payload,
+    ne.created_at
+FROM service_notifications sn
+JOIN notification_events ne ON ne.id = sn.event_id
+WHERE sn.status IN ('pending', 'failed');
@@ -0,0 +1,21 @@
+-- service_configs becomes the runtime channel registry: one row per named
+-- channel instance rather than one per service type. `source` tells channels
+-- declared in the config file ('config') from ones added at runtime.
+CREATE TABLE service_configs_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(50) UNIQUE NOT NULL,
+    service_type VARCHAR(20) NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    config_json TEXT NOT NULL, -- credentials are stored as 'enc:v1:...'
+    source VARCHAR(20) NOT NULL DEFAULT 'runtime',
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- the seeded placeholders carry no settings, so they are not carried over
+INSERT INTO service_configs_new (id, name, service_type, enabled, config_json, updated_at)
+SELECT id, service_type, service_type, COALESCE(enabled, TRUE), config_json, updated_at
+FROM service_configs
+WHERE config_json <> '{"enabled": false}';
+
+DROP TABLE service_configs;
+ALTER TABLE service_configs_new RENAME TO service_configs;
@@ -0,0 +1,9 @@
+-- X-GitHub-Delivery GUIDs of accepted webhooks, so redeliveries of the same
+-- request are recognised. Rows past the retention window are pruned.
+CREATE TABLE webhook_deliveries (
+    delivery_id TEXT PRIMARY KEY,
+    event_id INTEGER REFERENCES notification_events(id) ON DELETE SET NULL,
+    received_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_webhook_deliveries_received_at ON webhook_deliveries(received_at);
@@ -0,0 +1,11 @@
+-- GitHub webhooks that have pinged us, with what they are subscribed to and
+-- anything about their setup the service can't handle.
+CREATE TABLE webhook_hooks (
+    hook_id INTEGER PRIMARY KEY,
+    hook_type TEXT NOT NULL,
+    target TEXT,
+    events TEXT NOT NULL, -- JSON array
+    content_type TEXT,
+    problems TEXT NOT NULL, -- JSON array, empty when the hook looks right
+    pinged_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
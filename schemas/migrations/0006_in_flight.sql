@@ -0,0 +1,15 @@
+-- A claimed delivery is 'in_flight' until the worker records its outcome;
+-- next_attempt_at holds the lease expiry, after which the row is due again.
+DROP VIEW v_pending_notifications;
+CREATE VIEW v_pending_notifications AS
+SELECT
+    sn.id as notification_id,
+    sn.event_id,
+    sn.service_type,
+    sn.attempts,
+    sn.next_attempt_at,
+    ne.payload,
+    ne.created_at
+FROM service_notifications sn
+JOIN notification_events ne ON ne.id = sn.event_id
+WHERE sn.status IN ('pending', 'failed', 'in_flight');
@@ -0,0 +1,5 @@
+-- Webhooks that are acknowledged but not announced (unsupported events,
+-- routine actions) are kept as 'skipped' events with the reason, so the
+-- history shows everything that came in.
+ALTER TABLE notification_events ADD COLUMN status TEXT NOT NULL DEFAULT 'accepted';
+ALTER TABLE notification_events ADD COLUMN skip_reason TEXT;
@@ -0,0 +1,91 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{collections::BTreeSet, env, fs, path::Path};
+
+/// Reads a TOML or YAML config file, chosen by extension, and expands
+/// `${VAR}` references in its string values from the environment. Also
+/// returns the names of the variables that were referenced.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<(T, BTreeSet<String>)> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+    let mut value: Value = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?,
+        _ => bail!(
+            "Config file {} must end in .toml, .yaml or .yml",
+            path.display()
+        ),
+    };
+    let mut referenced = BTreeSet::new();
+    interpolate(&mut value, &mut referenced)?;
+
+    let config = serde_json::from_value(value)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+    Ok((config, referenced))
+}
+
+fn interpolate(value: &mut Value, referenced: &mut BTreeSet<String>) -> Result<()> {
+    match value {
+        Value::String(text) => *text = expand(text, referenced)?,
+        Value::Array(items) => items
+            .iter_mut()
+            .try_for_each(|item| interpolate(item, referenced))?,
+        Value::Object(table) => table
+            .values_mut()
+            .try_for_each(|item| interpolate(item, referenced))?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand(text: &str, referenced: &mut BTreeSet<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| anyhow!("Unterminated ${{...}} reference in config file"))?;
+
+        let name = &rest[start + 2..end];
+        let value = env_or_file(name)?.ok_or_else(|| {
+            anyhow!(
+                "Config file references ${{{}}}, but {} is not set",
+                name,
+                name
+            )
+        })?;
+        expanded.push_str(&value);
+        referenced.insert(name.to_string());
+        rest = &rest[end + 1..];
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Reads `name` from the environment, or from the file named by `<name>_FILE`
+/// when `name` is unset or blank, as Docker and Kubernetes mount secrets.
+pub fn env_or_file(name: &str) -> Result<Option<String>> {
+    let value = env::var(name).ok();
+    if value
+        .as_deref()
+        .is_some_and(|value| !value.trim().is_empty())
+    {
+        return Ok(value);
+    }
+    let Ok(path) = env::var(format!("{}_FILE", name)) else {
+        return Ok(value);
+    };
+
+    let value = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}_FILE {}", name, path))?;
+    // files usually end in a newline the value itself doesn't have
+    Ok(Some(value.trim_end_matches(['\r', '\n']).to_string()))
+}
@@ -1,10 +1,20 @@
+mod file;
+mod secret;
 pub mod settings;
 
+pub use secret::{scrub, Secret};
+
 pub use settings::{
+    ChannelConfig,
+    ChannelOptions,
     Config,
     EmailConfig,
+    LimitsConfig,
+    OutboxConfig,
+    PollerConfig,
     TelegramConfig, 
     DiscordConfig,
     SlackConfig,
+    WebhookSecret,
     WhatsAppConfig,
 };
\ No newline at end of file
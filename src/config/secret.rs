@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt, sync::RwLock};
+
+const REDACTED: &str = "[redacted]";
+
+/// Values shorter than this are left alone by [`scrub`], they would blank out
+/// ordinary words.
+const MIN_SCRUB_LEN: usize = 6;
+
+/// Every secret loaded so far, so they can be scrubbed from error text.
+static KNOWN: RwLock<BTreeSet<String>> = RwLock::new(BTreeSet::new());
+
+/// A credential from the config. `Debug` and `Display` print a placeholder;
+/// use [`Secret::expose`] where the real value is needed. Serializes as the
+/// plain value so channel configs can still be encrypted into storage.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        let value = value.into();
+        if value.trim().len() >= MIN_SCRUB_LEN {
+            KNOWN.write().unwrap().insert(value.clone());
+        }
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<Secret> for String {
+    fn from(secret: Secret) -> Self {
+        secret.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Replaces every known secret in `text`, e.g. a bot token that ended up in
+/// a request URL quoted by an HTTP error.
+pub fn scrub(text: &str) -> String {
+    let known = KNOWN.read().unwrap();
+    // longest first, so a secret containing another is replaced whole
+    let mut secrets: Vec<&String> = known.iter().filter(|s| text.contains(s.as_str())).collect();
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+    secrets.into_iter().fold(text.to_string(), |text, secret| {
+        text.replace(secret.as_str(), REDACTED)
+    })
+}
@@ -1,117 +1,752 @@
-use std::env;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    path::PathBuf,
+    str::FromStr,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 
+use super::{file, Secret};
+use crate::templates::TemplateRenderer;
 
 pub struct Config {
-    pub port: String,
-    pub webhook_secret: Option<String>,
-    pub whatsapp_config: Option<WhatsAppConfig>,
-    pub telegram_config: Option<TelegramConfig>,
-    pub discord_config: Option<DiscordConfig>,
-    pub slack_config: Option<SlackConfig>,
-    pub email_config: Option<EmailConfig>,
+    /// Path of the config file, if one was loaded.
+    pub config_file: Option<PathBuf>,
+    pub port: u16,
+    pub database_url: String,
+    /// Secrets accepted on incoming webhooks, tried in order.
+    pub webhook_secrets: Vec<WebhookSecret>,
+    pub allow_sha1_signature: bool,
+    /// Bearer token for the `/admin` routes; they are disabled without one.
+    pub admin_token: Option<Secret>,
+    /// Hex-encoded key that encrypts channel credentials in `service_configs`.
+    /// Without it, credentials of declared channels are not stored.
+    pub service_config_key: Option<Secret>,
+    /// Notification channels keyed by their unique name.
+    pub channels: BTreeMap<String, ChannelConfig>,
+    pub poller_config: Option<PollerConfig>,
+    pub outbox_config: OutboxConfig,
+    pub limits: LimitsConfig,
+    pub notify_timeout_secs: u64,
+    /// How long `X-GitHub-Delivery` GUIDs are remembered to drop redeliveries.
+    pub delivery_retention_secs: u64,
+    pub template_dir: Option<String>,
+    /// Inline templates keyed by template name, e.g. `followed.slack.body`.
+    pub templates: BTreeMap<String, String>,
 }
 
+/// Layout of `CONFIG_FILE`. Everything is optional so env vars alone are
+/// still a complete configuration.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    port: Option<u16>,
+    database_url: Option<String>,
+    webhook_secret: Option<Secret>,
+    webhook_secrets: Vec<FileSecret>,
+    allow_sha1_signature: Option<bool>,
+    service_config_key: Option<Secret>,
+    admin_token: Option<Secret>,
+    notify_timeout_secs: Option<u64>,
+    delivery_retention_secs: Option<u64>,
+    template_dir: Option<String>,
+    templates: BTreeMap<String, String>,
+    outbox: OutboxConfig,
+    limits: LimitsConfig,
+    // kept loose until env overrides are merged in, then checked strictly
+    poller: Map<String, Value>,
+    channels: BTreeMap<String, Map<String, Value>>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutboxConfig {
+    pub max_attempts: u32,
+    pub base_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+    pub poll_interval_secs: u64,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            base_backoff_secs: 30,
+            max_backoff_secs: 3600,
+            poll_interval_secs: 10,
+        }
+    }
+}
+
+/// A secret GitHub may sign webhooks with. Several can be active at once so
+/// the secret can be rotated without rejecting deliveries.
+#[derive(Clone)]
+pub struct WebhookSecret {
+    /// Identifies the secret in logs without revealing it.
+    pub name: String,
+    pub secret: Secret,
+    /// Unix time from which the secret is no longer accepted.
+    pub expires_at: Option<i64>,
+}
+
+impl WebhookSecret {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| OffsetDateTime::now_utc().unix_timestamp() >= expires_at)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileSecret {
+    #[serde(default)]
+    name: Option<String>,
+    secret: Secret,
+    /// RFC 3339, e.g. `2024-06-01T00:00:00Z`.
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+/// Limits on inbound `/webhook` traffic. A rate of 0 turns that limit off.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub per_ip_per_minute: u32,
+    pub per_ip_burst: u32,
+    pub global_per_second: u32,
+    pub global_burst: u32,
+    /// Requests handled at once; more are shed with a 503.
+    pub max_concurrency: usize,
+    pub max_body_bytes: usize,
+    /// Take the client IP from the last `X-Forwarded-For` entry, for use
+    /// behind a proxy that appends to it.
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            per_ip_per_minute: 60,
+            per_ip_burst: 20,
+            global_per_second: 50,
+            global_burst: 100,
+            max_concurrency: 32,
+            max_body_bytes: 1024 * 1024,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+/// One notification channel. The `kind` key picks the variant and may be
+/// left out when the channel is named after its kind.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ChannelConfig {
+    WhatsApp(WhatsAppConfig),
+    Telegram(TelegramConfig),
+    Discord(DiscordConfig),
+    Slack(SlackConfig),
+    Email(EmailConfig),
+}
+
+impl ChannelConfig {
+    pub fn options(&self) -> &ChannelOptions {
+        match self {
+            ChannelConfig::WhatsApp(c) => &c.options,
+            ChannelConfig::Telegram(c) => &c.options,
+            ChannelConfig::Discord(c) => &c.options,
+            ChannelConfig::Slack(c) => &c.options,
+            ChannelConfig::Email(c) => &c.options,
+        }
+    }
+}
+
+/// Delivery options every kind of channel accepts.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelOptions {
+    pub notify_unfollows: bool,
+    /// Overrides `Config::notify_timeout_secs` for this channel.
+    pub timeout_secs: Option<u64>,
+    /// Overrides the provider's default sending rate; 0 means unlimited.
+    pub rate_limit_per_minute: Option<u32>,
+    pub rate_limit_burst: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct EmailConfig {
     pub smtp_server: String,
     pub smtp_username: String,
-    pub smtp_password: String,
+    pub smtp_password: Secret,
     pub from_email: String,
     pub to_email: String,
+    #[serde(flatten)]
+    pub options: ChannelOptions,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PollerConfig {
+    pub github_token: Secret,
+    pub login: String,
+    #[serde(default = "PollerConfig::default_api_base_url")]
+    pub api_base_url: String,
+    #[serde(default = "PollerConfig::default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "PollerConfig::default_snapshot_path")]
+    pub snapshot_path: String,
+}
+
+impl PollerConfig {
+    fn default_api_base_url() -> String {
+        "https://api.github.com".to_string()
+    }
+
+    fn default_interval_secs() -> u64 {
+        300
+    }
+
+    fn default_snapshot_path() -> String {
+        "followers.json".to_string()
+    }
 }
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct TelegramConfig {
-    pub bot_token: String,
+    pub bot_token: Secret,
     pub chat_id: String,
+    #[serde(flatten)]
+    pub options: ChannelOptions,
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DiscordConfig {
-    pub webhook_url: String,
-    pub bot_token: String,
+    pub webhook_url: Secret,
+    pub bot_token: Secret,
+    #[serde(flatten)]
+    pub options: ChannelOptions,
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SlackConfig {
-    pub webhook_url: String,
+    pub webhook_url: Secret,
     pub channel: String,
-    pub bot_token: String,
+    pub bot_token: Secret,
+    #[serde(flatten)]
+    pub options: ChannelOptions,
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct WhatsAppConfig {
-    pub api_key: String,
+    pub api_key: Secret,
     pub phone_number: String,
+    #[serde(flatten)]
+    pub options: ChannelOptions,
 }
 
+/// Env vars mapped onto config fields, as `(variable, field)` pairs.
+type EnvFields = &'static [(&'static str, &'static str)];
+
+/// Env vars that fill in the channel named after each kind, as
+/// `(kind, prefix, fields)`. `<prefix>_NOTIFY_UNFOLLOWS`, `<prefix>_TIMEOUT_SECS`,
+/// `<prefix>_RATE_LIMIT_PER_MINUTE` and `<prefix>_RATE_LIMIT_BURST` work for
+/// every kind.
+const CHANNEL_ENV: &[(&str, &str, EnvFields)] = &[
+    (
+        "whatsapp",
+        "WHATSAPP",
+        &[
+            ("WHATSAPP_API_KEY", "api_key"),
+            ("WHATSAPP_PHONE_NUMBER", "phone_number"),
+        ],
+    ),
+    (
+        "telegram",
+        "TELEGRAM",
+        &[
+            ("TELEGRAM_BOT_TOKEN", "bot_token"),
+            ("TELEGRAM_CHAT_ID", "chat_id"),
+        ],
+    ),
+    (
+        "discord",
+        "DISCORD",
+        &[
+            ("DISCORD_WEBHOOK_URL", "webhook_url"),
+            ("DISCORD_BOT_TOKEN", "bot_token"),
+        ],
+    ),
+    (
+        "slack",
+        "SLACK",
+        &[
+            ("SLACK_WEBHOOK_URL", "webhook_url"),
+            ("SLACK_CHANNEL", "channel"),
+            ("SLACK_BOT_TOKEN", "bot_token"),
+        ],
+    ),
+    (
+        "email",
+        "EMAIL",
+        &[
+            ("SMTP_SERVER", "smtp_server"),
+            ("SMTP_USERNAME", "smtp_username"),
+            ("SMTP_PASSWORD", "smtp_password"),
+            ("FROM_EMAIL", "from_email"),
+            ("TO_EMAIL", "to_email"),
+        ],
+    ),
+];
+
 impl Config {
+    /// Loads `CONFIG_FILE` if set, then applies env vars on top of it.
     pub fn new() -> Result<Self> {
+        let config_file = Self::env_var("CONFIG_FILE").map(PathBuf::from);
+        let (file, referenced): (FileConfig, _) = match &config_file {
+            Some(path) => file::load(path)?,
+            None => Default::default(),
+        };
+
+        let webhook_secrets =
+            Self::load_webhook_secrets(file.webhook_secret, file.webhook_secrets)?;
+
+        let max_concurrency =
+            Self::env_number("LIMITS_MAX_CONCURRENCY")?.unwrap_or(file.limits.max_concurrency);
+        if max_concurrency == 0 {
+            bail!("LIMITS_MAX_CONCURRENCY (or limits.max_concurrency) must be at least 1");
+        }
+
+        let mut templates = file.templates;
+        templates.extend(Self::load_templates());
+
         Ok(Config {
-            port: env::var("PORT").unwrap_or_else(|_| "8080".to_string()),
-            webhook_secret: env::var("GITHUB_WEBHOOK_SECRET").ok(),
-            whatsapp_config: Self::load_whatsapp_config(),
-            telegram_config: Self::load_telegram_config(),
-            discord_config: Self::load_discord_config(),
-            slack_config: Self::load_slack_config(),
-            email_config: Self::load_email_config(),
+            config_file,
+            port: Self::env_number("PORT")?.or(file.port).unwrap_or(8080),
+            database_url: Self::env_var("DATABASE_URL")
+                .or(file.database_url)
+                .unwrap_or_else(|| "sqlite://database.sqlite".to_string()),
+            webhook_secrets,
+            allow_sha1_signature: Self::env_bool("GITHUB_WEBHOOK_ALLOW_SHA1")
+                .or(file.allow_sha1_signature)
+                .unwrap_or(false),
+            admin_token: Self::secret_var("ADMIN_TOKEN")?.or(file.admin_token),
+            service_config_key: Self::secret_var("SERVICE_CONFIG_KEY")?.or(file.service_config_key),
+            channels: Self::load_channels(file.channels, &referenced)?,
+            poller_config: Self::load_poller_config(file.poller)?,
+            notify_timeout_secs: Self::env_number("NOTIFY_TIMEOUT_SECS")?
+                .or(file.notify_timeout_secs)
+                .unwrap_or(10),
+            // GitHub offers redelivery for three days, keep a little longer
+            delivery_retention_secs: Self::env_number("WEBHOOK_DELIVERY_RETENTION_SECS")?
+                .or(file.delivery_retention_secs)
+                .unwrap_or(7 * 24 * 60 * 60),
+            template_dir: Self::env_var("TEMPLATE_DIR").or(file.template_dir),
+            templates,
+            outbox_config: OutboxConfig {
+                max_attempts: Self::env_number("OUTBOX_MAX_ATTEMPTS")?
+                    .unwrap_or(file.outbox.max_attempts),
+                base_backoff_secs: Self::env_number("OUTBOX_BASE_BACKOFF_SECS")?
+                    .unwrap_or(file.outbox.base_backoff_secs),
+                max_backoff_secs: Self::env_number("OUTBOX_MAX_BACKOFF_SECS")?
+                    .unwrap_or(file.outbox.max_backoff_secs),
+                poll_interval_secs: Self::env_number("OUTBOX_POLL_INTERVAL_SECS")?
+                    .unwrap_or(file.outbox.poll_interval_secs),
+            },
+            limits: LimitsConfig {
+                per_ip_per_minute: Self::env_number("LIMITS_PER_IP_PER_MINUTE")?
+                    .unwrap_or(file.limits.per_ip_per_minute),
+                per_ip_burst: Self::env_number("LIMITS_PER_IP_BURST")?
+                    .unwrap_or(file.limits.per_ip_burst),
+                global_per_second: Self::env_number("LIMITS_GLOBAL_PER_SECOND")?
+                    .unwrap_or(file.limits.global_per_second),
+                global_burst: Self::env_number("LIMITS_GLOBAL_BURST")?
+                    .unwrap_or(file.limits.global_burst),
+                max_concurrency,
+                max_body_bytes: Self::env_number("LIMITS_MAX_BODY_BYTES")?
+                    .unwrap_or(file.limits.max_body_bytes),
+                trust_forwarded_for: Self::env_bool("LIMITS_TRUST_FORWARDED_FOR")
+                    .unwrap_or(file.limits.trust_forwarded_for),
+            },
+        })
+    }
+
+    /// Unset and empty variables are treated alike so a blank line in `.env`
+    /// doesn't count as configuration.
+    fn env_var(key: &str) -> Option<String> {
+        env::var(key).ok().filter(|value| !value.trim().is_empty())
+    }
+
+    /// Like `env_var`, but also reads `<key>_FILE` for Docker and Kubernetes
+    /// secrets.
+    fn secret_var(key: &str) -> Result<Option<Secret>> {
+        Ok(file::env_or_file(key)?
+            .filter(|value| !value.trim().is_empty())
+            .map(Secret::new))
+    }
+
+    fn env_bool(key: &str) -> Option<bool> {
+        Self::env_var(key).map(|value| {
+            matches!(
+                value.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes"
+            )
         })
     }
-    fn load_telegram_config() -> Option<TelegramConfig> {
-        match (env::var("TELEGRAM_BOT_TOKEN"), env::var("TELEGRAM_CHAT_ID")) {
-            (Ok(bot_token), Ok(chat_id)) => Some(TelegramConfig {
-                bot_token,
-                chat_id,
-            }),
-            _ => None,
-        }
-    }
-
-    fn load_whatsapp_config() -> Option<WhatsAppConfig> {
-        match (env::var("WHATSAPP_API_KEY"), env::var("WHATSAPP_PHONE_NUMBER")) {
-            (Ok(api_key), Ok(phone_number)) => Some(WhatsAppConfig {
-                api_key,
-                phone_number,
-            }),
-            _ => None,
-        }
-    }
-    fn load_discord_config() -> Option<DiscordConfig> {
-        match (env::var("DISCORD_WEBHOOK_URL"), env::var("DISCORD_BOT_TOKEN")) {
-            (Ok(webhook_url), Ok(bot_token)) => Some(DiscordConfig {
-                webhook_url,
-                bot_token,
-            }),
-            _ => None,
-        }
-    }
-    fn load_slack_config() -> Option<SlackConfig> {
-        match (
-            env::var("SLACK_WEBHOOK_URL"),
-            env::var("SLACK_CHANNEL"),
-            env::var("SLACK_BOT_TOKEN"),
-        ) {
-            (Ok(webhook_url), Ok(channel), Ok(bot_token)) => Some(SlackConfig {
-                webhook_url,
-                channel,
-                bot_token,
-            }),
-            _ => None,
-        }
-    }
-    fn load_email_config() -> Option<EmailConfig> {
-        match (
-            env::var("SMTP_SERVER"),
-            env::var("SMTP_USERNAME"),
-            env::var("SMTP_PASSWORD"),
-            env::var("FROM_EMAIL"),
-            env::var("TO_EMAIL"),
-        ) {
-            (Ok(smtp_server), Ok(smtp_username), Ok(smtp_password), Ok(from_email), Ok(to_email)) => {
-                Some(EmailConfig {
-                    smtp_server,
-                    smtp_username,
-                    smtp_password,
-                    from_email,
-                    to_email,
-                })
+
+    fn env_number<T: FromStr>(key: &str) -> Result<Option<T>> {
+        Self::env_var(key)
+            .map(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("{} must be a non-negative number", key))
+            })
+            .transpose()
+    }
+
+    /// `GITHUB_WEBHOOK_SECRET` (or `webhook_secret`) comes first, then the
+    /// `webhook_secrets` list, then `GITHUB_WEBHOOK_OLD_SECRET`.
+    fn load_webhook_secrets(
+        primary: Option<Secret>,
+        listed: Vec<FileSecret>,
+    ) -> Result<Vec<WebhookSecret>> {
+        let mut secrets = Vec::new();
+        if let Some(secret) = Self::secret_var("GITHUB_WEBHOOK_SECRET")?.or(primary) {
+            secrets.push(WebhookSecret {
+                name: "webhook_secret".to_string(),
+                secret,
+                expires_at: None,
+            });
+        }
+
+        for (index, entry) in listed.into_iter().enumerate() {
+            let name = entry
+                .name
+                .unwrap_or_else(|| format!("webhook_secrets[{}]", index));
+            let expires_at = entry
+                .expires_at
+                .as_deref()
+                .map(Self::parse_timestamp)
+                .transpose()
+                .with_context(|| format!("Invalid expires_at for webhook secret {}", name))?;
+            secrets.push(WebhookSecret {
+                name,
+                secret: entry.secret,
+                expires_at,
+            });
+        }
+
+        if let Some(secret) = Self::secret_var("GITHUB_WEBHOOK_OLD_SECRET")? {
+            let expires_at = Self::env_var("GITHUB_WEBHOOK_OLD_SECRET_EXPIRES_AT")
+                .as_deref()
+                .map(Self::parse_timestamp)
+                .transpose()
+                .context("Invalid GITHUB_WEBHOOK_OLD_SECRET_EXPIRES_AT")?;
+            secrets.push(WebhookSecret {
+                name: "GITHUB_WEBHOOK_OLD_SECRET".to_string(),
+                secret,
+                expires_at,
+            });
+        }
+
+        if let Some(empty) = secrets.iter().find(|s| s.secret.expose().trim().is_empty()) {
+            bail!("Webhook secret {} must not be empty", empty.name);
+        }
+        if secrets.is_empty() {
+            bail!("GITHUB_WEBHOOK_SECRET (or webhook_secret in the config file) must be set to a non-empty value");
+        }
+        if secrets.iter().all(WebhookSecret::is_expired) {
+            let names: Vec<&str> = secrets.iter().map(|s| s.name.as_str()).collect();
+            bail!(
+                "All webhook secrets have expired ({}), configure a current one",
+                names.join(", ")
+            );
+        }
+        Ok(secrets)
+    }
+
+    fn parse_timestamp(value: &str) -> Result<i64> {
+        Ok(OffsetDateTime::parse(value.trim(), &Rfc3339)
+            .with_context(|| format!("{} is not an RFC 3339 timestamp", value))?
+            .unix_timestamp())
+    }
+
+    /// `TEMPLATE_FOLLOWED_SLACK_BODY` becomes the `followed.slack.body` template.
+    fn load_templates() -> BTreeMap<String, String> {
+        env::vars()
+            .filter(|(key, _)| key != "TEMPLATE_DIR")
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix("TEMPLATE_")?;
+                Some((Self::template_name(name), value))
+            })
+            .collect()
+    }
+
+    /// Event names and channel names may contain underscores themselves, so
+    /// the event is matched against the known ones rather than split off.
+    fn template_name(var: &str) -> String {
+        let var = var.to_ascii_lowercase();
+        let Some((rest, part)) = var.rsplit_once('_') else {
+            return var;
+        };
+
+        // longest first, should one event name ever prefix another
+        let event = TemplateRenderer::events()
+            .filter(|event| {
+                rest.strip_prefix(event)
+                    .is_some_and(|tail| tail.is_empty() || tail.starts_with('_'))
+            })
+            .max_by_key(|event| event.len());
+        match event {
+            Some(event) if rest.len() > event.len() => {
+                format!("{}.{}.{}", event, &rest[event.len() + 1..], part)
+            }
+            Some(event) => format!("{}.{}", event, part),
+            // unknown event, left for template validation to report
+            None => var.replace('_', "."),
+        }
+    }
+
+    fn load_poller_config(mut table: Map<String, Value>) -> Result<Option<PollerConfig>> {
+        Self::overlay_env(
+            &mut table,
+            &[
+                ("GITHUB_TOKEN", "github_token"),
+                ("GITHUB_LOGIN", "login"),
+                ("GITHUB_API_URL", "api_base_url"),
+                ("FOLLOWER_SNAPSHOT_PATH", "snapshot_path"),
+            ],
+        )?;
+        if let Some(secs) = Self::env_number::<u64>("FOLLOWER_POLL_INTERVAL_SECS")? {
+            table.insert("interval_secs".to_string(), secs.into());
+        }
+
+        if table.is_empty() {
+            return Ok(None);
+        }
+        let config: PollerConfig = serde_json::from_value(Value::Object(table))
+            .context("Follower poller is partly configured (GITHUB_TOKEN and GITHUB_LOGIN are both required)")?;
+        if config.interval_secs == 0 {
+            bail!(
+                "FOLLOWER_POLL_INTERVAL_SECS (or interval_secs under [poller]) must be at least 1"
+            );
+        }
+        Ok(Some(config))
+    }
+
+    /// Variables the config file already interpolates into a channel are
+    /// left out of the kind-named channel they would otherwise configure.
+    fn load_channels(
+        mut tables: BTreeMap<String, Map<String, Value>>,
+        referenced: &BTreeSet<String>,
+    ) -> Result<BTreeMap<String, ChannelConfig>> {
+        for (kind, prefix, vars) in CHANNEL_ENV {
+            let vars: Vec<_> = vars
+                .iter()
+                .filter(|(key, _)| !referenced.contains(*key))
+                .copied()
+                .collect();
+            let mut overrides = Map::new();
+            Self::overlay_env(&mut overrides, &vars)?;
+            if let Some(notify) = Self::env_bool(&format!("{}_NOTIFY_UNFOLLOWS", prefix)) {
+                overrides.insert("notify_unfollows".to_string(), notify.into());
+            }
+            if let Some(secs) = Self::env_number::<u64>(&format!("{}_TIMEOUT_SECS", prefix))? {
+                overrides.insert("timeout_secs".to_string(), secs.into());
+            }
+            for field in ["rate_limit_per_minute", "rate_limit_burst"] {
+                let key = format!("{}_{}", prefix, field.to_ascii_uppercase());
+                if let Some(value) = Self::env_number::<u32>(&key)? {
+                    overrides.insert(field.to_string(), value.into());
+                }
+            }
+
+            if !overrides.is_empty() {
+                tables
+                    .entry(kind.to_string())
+                    .or_default()
+                    .extend(overrides);
             }
-            _ => None,
         }
+
+        tables
+            .into_iter()
+            .map(|(name, mut table)| {
+                table
+                    .entry("kind")
+                    .or_insert_with(|| Value::String(name.clone()));
+                let channel = serde_json::from_value(Value::Object(table))
+                    .with_context(|| format!("Channel {} is partly configured or invalid", name))?;
+                Ok((name, channel))
+            })
+            .collect()
     }
-}
\ No newline at end of file
+
+    /// Each variable may also be given as `<variable>_FILE`. Values stay
+    /// plain here; only fields typed as [`Secret`] are scrubbed once parsed.
+    fn overlay_env(table: &mut Map<String, Value>, vars: &[(&str, &str)]) -> Result<()> {
+        for (key, field) in vars {
+            let value = file::env_or_file(key)?.filter(|value| !value.trim().is_empty());
+            if let Some(value) = value {
+                table.insert(field.to_string(), Value::String(value));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// Defaults with one webhook secret and no channels, for tests.
+    pub(crate) fn for_tests() -> Self {
+        Self {
+            config_file: None,
+            port: 8080,
+            database_url: "sqlite::memory:".to_string(),
+            webhook_secrets: vec![WebhookSecret {
+                name: "webhook_secret".to_string(),
+                secret: Secret::new("test-webhook-secret"),
+                expires_at: None,
+            }],
+            allow_sha1_signature: false,
+            admin_token: None,
+            service_config_key: None,
+            channels: BTreeMap::new(),
+            poller_config: None,
+            outbox_config: OutboxConfig::default(),
+            limits: LimitsConfig::default(),
+            notify_timeout_secs: 10,
+            delivery_retention_secs: 7 * 24 * 60 * 60,
+            template_dir: None,
+            templates: BTreeMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::scrub;
+    use std::sync::Mutex;
+
+    // tests that set env vars take turns, `Config::new` reads the whole environment
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn template_names_keep_underscores_in_events_and_channels() {
+        assert_eq!(Config::template_name("FOLLOWED_BODY"), "followed.body");
+        assert_eq!(
+            Config::template_name("FOLLOWED_SLACK_BODY"),
+            "followed.slack.body"
+        );
+        assert_eq!(
+            Config::template_name("PULL_REQUEST_TITLE"),
+            "pull_request.title"
+        );
+        assert_eq!(
+            Config::template_name("PULL_REQUEST_TEAM_CHAT_BODY"),
+            "pull_request.team_chat.body"
+        );
+    }
+
+    #[test]
+    fn example_config_loads() {
+        let _env = ENV_LOCK.lock().unwrap();
+        let vars = [
+            ("GITHUB_WEBHOOK_SECRET", "example-webhook-secret"),
+            ("SERVICE_CONFIG_KEY", "ab"),
+            ("TELEGRAM_BOT_TOKEN", "example-telegram-token"),
+            (
+                "SLACK_OPS_WEBHOOK_URL",
+                "https://hooks.slack.com/services/example",
+            ),
+            ("SLACK_OPS_BOT_TOKEN", "example-slack-token"),
+        ];
+        for (key, value) in vars {
+            env::set_var(key, value);
+        }
+        env::set_var(
+            "CONFIG_FILE",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/config.example.toml"),
+        );
+
+        let config = Config::new();
+        for (key, _) in vars {
+            env::remove_var(key);
+        }
+        env::remove_var("CONFIG_FILE");
+
+        let config = config.unwrap();
+        let names: Vec<&str> = config.channels.keys().map(String::as_str).collect();
+        assert_eq!(names, ["slack-ops", "telegram"]);
+    }
+
+    #[test]
+    fn interpolated_variables_do_not_configure_the_kind_channel() {
+        let _env = ENV_LOCK.lock().unwrap();
+        env::set_var("SLACK_BOT_TOKEN", "shared-slack-token");
+
+        let table = serde_json::json!({
+            "kind": "slack",
+            "webhook_url": "https://hooks.slack.com/services/example",
+            "channel": "#ops",
+            "bot_token": "shared-slack-token",
+        });
+        let tables =
+            BTreeMap::from([("slack-ops".to_string(), table.as_object().unwrap().clone())]);
+        let referenced = BTreeSet::from(["SLACK_BOT_TOKEN".to_string()]);
+        let channels = Config::load_channels(tables.clone(), &referenced);
+        // without the reference it would configure a partial `slack` channel
+        let unreferenced = Config::load_channels(tables, &BTreeSet::new());
+        env::remove_var("SLACK_BOT_TOKEN");
+
+        let channels = channels.unwrap();
+        assert_eq!(channels.keys().collect::<Vec<_>>(), ["slack-ops"]);
+        assert!(unreferenced.is_err());
+    }
+
+    #[test]
+    fn only_secret_fields_are_scrubbed() {
+        let _env = ENV_LOCK.lock().unwrap();
+        env::set_var("TELEGRAM_BOT_TOKEN", "scrub-test-telegram-token");
+        env::set_var("TELEGRAM_CHAT_ID", "scrub-test-chat-id");
+
+        let channels = Config::load_channels(BTreeMap::new(), &BTreeSet::new());
+        env::remove_var("TELEGRAM_BOT_TOKEN");
+        env::remove_var("TELEGRAM_CHAT_ID");
+
+        assert!(channels.unwrap().contains_key("telegram"));
+        assert_eq!(
+            scrub("token scrub-test-telegram-token, chat scrub-test-chat-id"),
+            "token [redacted], chat scrub-test-chat-id"
+        );
+    }
+
+    #[test]
+    fn channel_options_are_shared_and_still_strict() {
+        let table = serde_json::json!({
+            "kind": "discord",
+            "webhook_url": "https://discord.com/api/webhooks/1/example",
+            "bot_token": "example-discord-token",
+            "notify_unfollows": true,
+            "timeout_secs": 5,
+        });
+        let channel: ChannelConfig = serde_json::from_value(table.clone()).unwrap();
+        let options = channel.options();
+        assert!(options.notify_unfollows);
+        assert_eq!(options.timeout_secs, Some(5));
+        assert_eq!(options.rate_limit_per_minute, None);
+
+        let mut misspelled = table;
+        misspelled["notify_unfollow"] = true.into();
+        assert!(serde_json::from_value::<ChannelConfig>(misspelled).is_err());
+    }
+}
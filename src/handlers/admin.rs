@@ -0,0 +1,336 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, Request},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Arc};
+
+use super::{health::channel_reports, HandlerError, HandlerResult};
+use crate::{
+    state::AppState,
+    storage::{DeliveryRecord, EventRecord, HistoryFilter, Page},
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+
+/// Routes under `/admin`, all behind `Authorization: Bearer <token>`.
+pub fn router(token: String) -> Router<AppState> {
+    Router::new()
+        .route("/events", get(list_events))
+        .route("/events/:id", get(get_event))
+        .route("/events/:id/resend", post(resend_event))
+        .route("/events/:id/cancel", post(cancel_event))
+        .route("/deliveries", get(list_deliveries))
+        .route("/deliveries/:id", get(get_delivery))
+        .route("/deliveries/:id/resend", post(resend_delivery))
+        .route("/deliveries/:id/cancel", post(cancel_delivery))
+        .route("/channels", get(channel_status))
+        .route("/hooks", get(list_hooks))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            require_token,
+        ))
+}
+
+async fn require_token(
+    State(token): State<Arc<str>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match presented {
+        Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => HandlerError::AuthenticationError("Invalid admin token".into()).into_response(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    since: Option<String>,
+    until: Option<String>,
+    login: Option<String>,
+    status: Option<String>,
+    channel: Option<String>,
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+impl HistoryQuery {
+    async fn parse(self, state: &AppState) -> HandlerResult<(HistoryFilter, Page)> {
+        for timestamp in [&self.since, &self.until].into_iter().flatten() {
+            let valid = state
+                .db
+                .is_valid_timestamp(timestamp)
+                .await
+                .map_err(database_error)?;
+            if !valid {
+                return Err(HandlerError::ValidationError(format!(
+                    "Invalid timestamp {}",
+                    timestamp
+                )));
+            }
+        }
+
+        let status = self
+            .status
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e: anyhow::Error| HandlerError::ValidationError(e.to_string()))?;
+
+        let page = Page {
+            number: self.page.unwrap_or(1).max(1),
+            size: self
+                .per_page
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        };
+
+        Ok((
+            HistoryFilter {
+                since: self.since,
+                until: self.until,
+                login: self.login,
+                status,
+                channel: self.channel,
+            },
+            page,
+        ))
+    }
+}
+
+#[derive(Serialize)]
+struct Paginated<T> {
+    items: Vec<T>,
+    page: u32,
+    per_page: u32,
+    total: i64,
+}
+
+#[derive(Serialize)]
+struct EventWithDeliveries {
+    #[serde(flatten)]
+    event: EventRecord,
+    deliveries: Vec<DeliveryRecord>,
+}
+
+async fn list_events(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> HandlerResult<impl IntoResponse> {
+    let (filter, page) = query.parse(&state).await?;
+    let (events, total) = state
+        .db
+        .events(&filter, page)
+        .await
+        .map_err(database_error)?;
+
+    let ids: Vec<i64> = events.iter().map(|event| event.id).collect();
+    let mut deliveries: BTreeMap<i64, Vec<DeliveryRecord>> = BTreeMap::new();
+    for delivery in state.db.deliveries_of(&ids).await.map_err(database_error)? {
+        deliveries
+            .entry(delivery.event_id)
+            .or_default()
+            .push(delivery);
+    }
+
+    let items = events
+        .into_iter()
+        .map(|event| EventWithDeliveries {
+            deliveries: deliveries.remove(&event.id).unwrap_or_default(),
+            event,
+        })
+        .collect();
+
+    Ok(Json(Paginated {
+        items,
+        page: page.number,
+        per_page: page.size,
+        total,
+    }))
+}
+
+async fn get_event(
+    State(state): State<AppState>,
+    Path(event_id): Path<i64>,
+) -> HandlerResult<impl IntoResponse> {
+    let event = find_event(&state, event_id).await?;
+    let deliveries = state
+        .db
+        .deliveries_of(&[event_id])
+        .await
+        .map_err(database_error)?;
+
+    Ok(Json(EventWithDeliveries { event, deliveries }))
+}
+
+async fn list_deliveries(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> HandlerResult<impl IntoResponse> {
+    let (filter, page) = query.parse(&state).await?;
+    let (items, total) = state
+        .db
+        .deliveries(&filter, page)
+        .await
+        .map_err(database_error)?;
+
+    Ok(Json(Paginated {
+        items,
+        page: page.number,
+        per_page: page.size,
+        total,
+    }))
+}
+
+async fn get_delivery(
+    State(state): State<AppState>,
+    Path(notification_id): Path<i64>,
+) -> HandlerResult<impl IntoResponse> {
+    Ok(Json(find_delivery(&state, notification_id).await?))
+}
+
+async fn resend_event(
+    State(state): State<AppState>,
+    Path(event_id): Path<i64>,
+) -> HandlerResult<impl IntoResponse> {
+    find_event(&state, event_id).await?;
+    let resent = state
+        .db
+        .resend(event_id, None)
+        .await
+        .map_err(database_error)?;
+    state.queue.schedule(event_id);
+
+    Ok(Json(json!({ "event_id": event_id, "resent": resent })))
+}
+
+async fn resend_delivery(
+    State(state): State<AppState>,
+    Path(notification_id): Path<i64>,
+) -> HandlerResult<impl IntoResponse> {
+    let delivery = find_delivery(&state, notification_id).await?;
+    let resent = state
+        .db
+        .resend(delivery.event_id, Some(notification_id))
+        .await
+        .map_err(database_error)?;
+    if resent == 0 {
+        return Err(HandlerError::ValidationError(format!(
+            "Delivery {} has not finished ({}) and can't be resent yet",
+            notification_id, delivery.status
+        )));
+    }
+    state.queue.schedule(delivery.event_id);
+
+    Ok(Json(
+        json!({ "event_id": delivery.event_id, "resent": resent }),
+    ))
+}
+
+async fn cancel_event(
+    State(state): State<AppState>,
+    Path(event_id): Path<i64>,
+) -> HandlerResult<impl IntoResponse> {
+    find_event(&state, event_id).await?;
+    let outcome = state
+        .db
+        .cancel(event_id, None)
+        .await
+        .map_err(database_error)?;
+
+    Ok(Json(json!({
+        "event_id": event_id,
+        "cancelled": outcome.cancelled,
+        "in_flight": outcome.in_flight,
+    })))
+}
+
+async fn cancel_delivery(
+    State(state): State<AppState>,
+    Path(notification_id): Path<i64>,
+) -> HandlerResult<impl IntoResponse> {
+    let delivery = find_delivery(&state, notification_id).await?;
+    let outcome = state
+        .db
+        .cancel(delivery.event_id, Some(notification_id))
+        .await
+        .map_err(database_error)?;
+    if outcome.in_flight > 0 {
+        return Err(HandlerError::ValidationError(format!(
+            "Delivery {} is being sent and can no longer be cancelled",
+            notification_id
+        )));
+    }
+    if outcome.cancelled == 0 {
+        return Err(HandlerError::ValidationError(format!(
+            "Delivery {} is {} and can no longer be cancelled",
+            notification_id, delivery.status
+        )));
+    }
+
+    Ok(Json(
+        json!({ "event_id": delivery.event_id, "cancelled": outcome.cancelled }),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusQuery {
+    #[serde(default)]
+    probe: bool,
+}
+
+/// Each channel's recent delivery record, plus a live probe with `?probe=true`.
+async fn channel_status(
+    State(state): State<AppState>,
+    Query(query): Query<StatusQuery>,
+) -> HandlerResult<impl IntoResponse> {
+    Ok(Json(channel_reports(&state, query.probe).await))
+}
+
+/// GitHub webhooks that have pinged us, with any setup problems found.
+async fn list_hooks(State(state): State<AppState>) -> HandlerResult<impl IntoResponse> {
+    Ok(Json(state.db.hooks().await.map_err(database_error)?))
+}
+
+async fn find_event(state: &AppState, event_id: i64) -> HandlerResult<EventRecord> {
+    state
+        .db
+        .event(event_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| HandlerError::NotFoundError(format!("Event {} not found", event_id)))
+}
+
+async fn find_delivery(state: &AppState, notification_id: i64) -> HandlerResult<DeliveryRecord> {
+    state
+        .db
+        .delivery(notification_id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| {
+            HandlerError::NotFoundError(format!("Delivery {} not found", notification_id))
+        })
+}
+
+fn database_error(e: anyhow::Error) -> HandlerError {
+    HandlerError::DatabaseError(format!("{:#}", e))
+}
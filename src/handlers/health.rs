@@ -0,0 +1,110 @@
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use futures::future::join_all;
+use serde::Serialize;
+use serde_json::json;
+
+use super::HandlerError;
+use crate::{
+    services::{health::ChannelStatus, ChannelInfo},
+    state::AppState,
+};
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness: the database answers and at least one channel can deliver.
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let mut problems = Vec::new();
+
+    if let Err(e) = state.db.ping().await {
+        problems.push(format!("{:#}", e));
+    }
+    if !state
+        .manager
+        .load()
+        .list()
+        .iter()
+        .any(|channel| channel.enabled)
+    {
+        problems.push("No notification channel is enabled".to_string());
+    }
+
+    if problems.is_empty() {
+        (StatusCode::OK, Json(json!({ "status": "ready" })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "unavailable", "problems": problems })),
+        )
+    }
+}
+
+/// Prometheus scrape endpoint.
+pub async fn metrics(State(state): State<AppState>) -> Result<impl IntoResponse, HandlerError> {
+    let body = state
+        .metrics
+        .render(&state.db)
+        .await
+        .map_err(|e| HandlerError::InternalError(format!("{:#}", e)))?;
+
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], body))
+}
+
+/// Each channel's recent delivery record. Read-only and always available;
+/// live probes are under `/admin/channels?probe=true`.
+pub async fn channels(State(state): State<AppState>) -> impl IntoResponse {
+    Json(channel_reports(&state, false).await)
+}
+
+#[derive(Serialize)]
+pub(super) struct ChannelReport {
+    #[serde(flatten)]
+    info: ChannelInfo,
+    #[serde(flatten)]
+    status: ChannelStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probe: Option<ProbeResult>,
+}
+
+#[derive(Serialize)]
+struct ProbeResult {
+    ok: bool,
+    error: Option<String>,
+}
+
+pub(super) async fn channel_reports(state: &AppState, probe: bool) -> Vec<ChannelReport> {
+    let manager = state.manager.load_full();
+    let channels = manager.list();
+
+    let probes = join_all(channels.iter().map(|channel| {
+        let manager = &manager;
+        async move {
+            if !probe {
+                return None;
+            }
+            let result = manager.health_check(&channel.name).await;
+            Some(ProbeResult {
+                ok: result.is_ok(),
+                error: result.err().map(|e| format!("{:#}", e)),
+            })
+        }
+    }))
+    .await;
+
+    channels
+        .into_iter()
+        .zip(probes)
+        .map(|(info, probe)| ChannelReport {
+            status: state.health.status(&info.name),
+            info,
+            probe,
+        })
+        .collect()
+}
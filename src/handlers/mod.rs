@@ -5,12 +5,15 @@ use axum::{
 };
 use serde_json::json;
 
+pub mod admin;
+pub mod health;
 pub mod webhook;
 
 #[derive(Debug)]
 pub enum HandlerError {
     ValidationError(String),
     AuthenticationError(String),
+    NotFoundError(String),
     InternalError(String),
     DatabaseError(String),
     NotificationError(String),
@@ -21,6 +24,7 @@ impl IntoResponse for HandlerError {
         let (status, error_message) = match self {
             HandlerError::ValidationError(msg) => (StatusCode::BAD_REQUEST, msg),
             HandlerError::AuthenticationError(msg) => (StatusCode::UNAUTHORIZED, msg),
+            HandlerError::NotFoundError(msg) => (StatusCode::NOT_FOUND, msg),
             HandlerError::NotificationError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             HandlerError::DatabaseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             HandlerError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
@@ -1,80 +1,638 @@
 use axum::{
-    extract::{Json, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
+    body::Bytes,
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
 };
 use hmac::{Hmac, Mac};
-use notify_rust::Notification;
+use notify_rust::Notification as DesktopNotification;
+use serde::Deserialize;
+use serde_json::json;
+use sha1::Sha1;
 use sha2::Sha256;
-use std::sync::Arc;
-use tracing::{info, warn};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tracing::{debug, error, info, warn};
 
 use super::HandlerError;
-use crate::{models::FollowerEvent, services::NotificationManager};
+use sqlx::types::Json as SqlJson;
+
+use crate::{
+    config::WebhookSecret,
+    models::{GitHubEvent, Hook, PingEvent, Sender, GITHUB_EVENTS},
+    state::AppState,
+    storage::{Enqueued, HookRecord, SkippedEvent},
+};
 
 pub async fn handle_webhook(
-    State(manager): State<Arc<NotificationManager>>,
+    State(state): State<AppState>,
     headers: HeaderMap,
-    Json(event): Json<FollowerEvent>,
-) -> Result<impl IntoResponse, HandlerError> {
-    // convert the event back to JSON string for signature verification
-    let body_str = serde_json::to_string(&event)
-        .map_err(|e| HandlerError::ValidationError(format!("Failed to serialize event: {}", e)))?;
-
-    // verify GitHub signature
-    let signature = headers
-        .get("X-Hub-Signature-256")
-        .and_then(|sig| sig.to_str().ok())
-        .map(|sig| sig.trim_start_matches("sha256="))
-        .ok_or_else(|| HandlerError::AuthenticationError("Missing signature header".into()))?;
-
-    // verify webhook signature
-    verify_signature(&body_str, signature, &manager.webhook_secret)
-        .map_err(|e| HandlerError::AuthenticationError(format!("Invalid signature: {}", e)))?;
-
-    // process the event
-    match event.action.as_str() {
-        "followed" => {
-            info!("New follower: {}", event.sender.login);
-
-            let title = "New GitHub Follower!";
-            let message = format!(
-                "User {} is now following you!\nProfile: {}",
-                event.sender.login, event.sender.html_url
-            );
+    body: Bytes,
+) -> Result<Response, HandlerError> {
+    // the signature covers the exact bytes GitHub sent, so check it before parsing
+    let manager = state.manager.load();
+    let verified = verify_request(
+        &headers,
+        &body,
+        &manager.webhook_secrets,
+        manager.allow_sha1_signature,
+    );
+    if let Err(e) = verified {
+        // unverified headers are attacker controlled, keep them out of the labels
+        state.metrics.record_webhook("unverified", "unknown", false);
+        return Err(e);
+    }
+
+    let github_event = headers
+        .get("X-GitHub-Event")
+        .and_then(|value| value.to_str().ok());
+    let body = match event_payload(&headers, body) {
+        Ok(body) => body,
+        Err(e) => {
+            state
+                .metrics
+                .record_webhook(github_event.unwrap_or("unknown"), "unknown", true);
+            return Err(e);
+        }
+    };
+
+    if github_event == Some("ping") {
+        state.metrics.record_webhook("ping", "none", true);
+        return handle_ping(&state, &body).await;
+    }
+
+    let parsed = GitHubEvent::parse(github_event, &body);
+    let (name, action) = match &parsed {
+        Ok(Some(event)) => (event.name(), event.action().unwrap_or("none").to_string()),
+        _ => (github_event.unwrap_or("unknown"), "unknown".to_string()),
+    };
+    state.metrics.record_webhook(name, &action, true);
+
+    let delivery_id = headers
+        .get("X-GitHub-Delivery")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty());
+
+    // unknown events and routine actions are acknowledged so GitHub doesn't flag the hook,
+    // and kept in the history as skipped
+    let event = match parsed
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid event payload: {}", e)))?
+    {
+        Some(event) if event.is_announced() => event,
+        Some(event) => {
+            debug!("Ignoring {} event with action {}", name, action);
+            let reason = format!("action {} is not announced", action);
+            let skipped = SkippedEvent {
+                event_type: event.key(),
+                sender: Some(event.sender()),
+                payload: &String::from_utf8_lossy(&body),
+                reason: &reason,
+            };
+            record_skipped(&state, &skipped, delivery_id).await;
+            return Ok(StatusCode::NO_CONTENT.into_response());
+        }
+        None => {
+            info!("Ignoring unsupported {} event", name);
+            let sender = payload_sender(&body);
+            let skipped = SkippedEvent {
+                event_type: name,
+                sender: sender.as_ref(),
+                payload: &String::from_utf8_lossy(&body),
+                reason: "unsupported event",
+            };
+            record_skipped(&state, &skipped, delivery_id).await;
+            return Ok(StatusCode::NO_CONTENT.into_response());
+        }
+    };
+
+    // delivery happens in the background, so GitHub only ever sees ingestion errors
+    match process_event(&state, &event, delivery_id).await? {
+        Enqueued::Created(event_id) => Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({ "status": "accepted", "event_id": event_id })),
+        )
+            .into_response()),
+        Enqueued::Duplicate(event_id) => Ok((
+            StatusCode::OK,
+            Json(json!({ "status": "duplicate", "event_id": event_id })),
+        )
+            .into_response()),
+    }
+}
+
+/// Keeps an acknowledged but unannounced webhook in the history. GitHub gets
+/// a 204 either way, so a storage error is only logged.
+async fn record_skipped(state: &AppState, skipped: &SkippedEvent<'_>, delivery_id: Option<&str>) {
+    let recorded = state
+        .db
+        .record_skipped(skipped, delivery_id, state.delivery_retention_secs)
+        .await;
+    match recorded {
+        Ok(Enqueued::Created(_)) => {}
+        Ok(Enqueued::Duplicate(_)) => info!(
+            "Ignoring redelivered webhook {}",
+            delivery_id.unwrap_or_default()
+        ),
+        Err(e) => warn!(
+            "Failed to record skipped {} event: {:#}",
+            skipped.event_type, e
+        ),
+    }
+}
+
+/// The sender of an event we don't model, if the payload has a usable one.
+fn payload_sender(body: &[u8]) -> Option<Sender> {
+    #[derive(Deserialize)]
+    struct Payload {
+        sender: Sender,
+    }
+    serde_json::from_slice::<Payload>(body)
+        .ok()
+        .map(|payload| payload.sender)
+}
+
+#[derive(Deserialize)]
+struct FormPayload {
+    payload: String,
+}
+
+/// The event JSON, which form-encoded hooks wrap in a `payload` field.
+fn event_payload(headers: &HeaderMap, body: Bytes) -> Result<Bytes, HandlerError> {
+    let is_form = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .trim()
+                .to_ascii_lowercase()
+                .starts_with("application/x-www-form-urlencoded")
+        });
+    if !is_form {
+        return Ok(body);
+    }
+
+    let form: FormPayload = serde_urlencoded::from_bytes(&body)
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid form payload: {}", e)))?;
+    Ok(Bytes::from(form.payload))
+}
+
+/// Answers GitHub's ping and remembers how the hook is set up, warning about
+/// anything that would keep its events from getting through.
+async fn handle_ping(state: &AppState, body: &[u8]) -> Result<Response, HandlerError> {
+    let ping: PingEvent = serde_json::from_slice(body)
+        .map_err(|e| HandlerError::ValidationError(format!("Invalid ping payload: {}", e)))?;
+
+    let target = ping.target().unwrap_or("unknown target");
+    info!(
+        "Webhook {} for {} pinged: {}",
+        ping.hook_id, target, ping.zen
+    );
+    let problems = hook_problems(&ping.hook);
+    for problem in &problems {
+        warn!("Webhook {} for {}: {}", ping.hook_id, target, problem);
+    }
+
+    state
+        .db
+        .record_hook(&HookRecord {
+            hook_id: ping.hook_id,
+            hook_type: ping.hook.hook_type.clone(),
+            target: ping.target().map(str::to_string),
+            events: SqlJson(ping.hook.events.clone()),
+            content_type: ping.hook.config.content_type.clone(),
+            problems: SqlJson(problems.clone()),
+            pinged_at: None,
+        })
+        .await
+        .map_err(|e| HandlerError::DatabaseError(format!("{:#}", e)))?;
+
+    Ok(Json(json!({
+        "status": "pong",
+        "hook_id": ping.hook_id,
+        "problems": problems,
+    }))
+    .into_response())
+}
+
+fn hook_problems(hook: &Hook) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !hook.active {
+        problems.push("Hook is inactive".to_string());
+    }
+
+    if hook.config.skips_tls_verification() {
+        problems
+            .push("Hook has SSL verification disabled, payloads could be intercepted".to_string());
+    }
 
-            // send notifications
-            manager
-                .notify_all(&title, &message)
+    let ignored: Vec<&str> = hook
+        .events
+        .iter()
+        .map(String::as_str)
+        .filter(|event| *event != "*" && !GITHUB_EVENTS.contains(event))
+        .collect();
+    if ignored.len() == hook.events.len() {
+        problems.push(format!(
+            "Hook subscribes to none of the supported events ({})",
+            GITHUB_EVENTS.join(", ")
+        ));
+    } else if !ignored.is_empty() {
+        problems.push(format!(
+            "Hook subscribes to events that will be ignored: {}",
+            ignored.join(", ")
+        ));
+    }
+
+    problems
+}
+
+/// Records an event in the outbox and queues it for delivery.
+/// Shared by the webhook endpoint and the follower poller; only webhooks
+/// carry a delivery id to deduplicate on.
+pub async fn process_event(
+    state: &AppState,
+    event: &GitHubEvent,
+    delivery_id: Option<&str>,
+) -> Result<Enqueued, HandlerError> {
+    let templates = state.templates.load();
+    if !templates.supports(event) {
+        warn!("Unsupported event: {}", event.key());
+        return Err(HandlerError::ValidationError(
+            "Unsupported event action".into(),
+        ));
+    }
+
+    let channels = state.manager.load().channels_for(event.key());
+    let enqueued = match delivery_id {
+        Some(delivery_id) => {
+            state
+                .db
+                .enqueue_webhook(delivery_id, state.delivery_retention_secs, event, &channels)
                 .await
-                .map_err(|e| HandlerError::NotificationError(e.to_string()))?;
-
-            // show desktop notification
-            if let Err(e) = Notification::new()
-                .summary(title)
-                .body(&message)
-                .icon("github")
-                .show()
-            {
-                warn!("Failed to show desktop notification: {}", e);
-            }
+        }
+        None => state
+            .db
+            .enqueue_event(event, &channels)
+            .await
+            .map(Enqueued::Created),
+    }
+    .map_err(|e| HandlerError::DatabaseError(e.to_string()))?;
 
-            Ok(StatusCode::OK)
+    let event_id = match enqueued {
+        Enqueued::Created(event_id) => event_id,
+        Enqueued::Duplicate(_) => {
+            info!(
+                "Ignoring redelivered webhook {}",
+                delivery_id.unwrap_or_default()
+            );
+            return Ok(enqueued);
         }
-        _ => {
-            warn!("Unsupported event action: {}", event.action);
-            Err(HandlerError::ValidationError(
-                "Unsupported event action".into(),
-            ))
+    };
+
+    match event.key() {
+        "followed" => info!("New follower: {}", event.sender().login),
+        "unfollowed" => info!("Lost follower: {}", event.sender().login),
+        key => info!("New {} event from {}", key, event.sender().login),
+    }
+
+    state.queue.schedule(event_id);
+
+    // show desktop notification; it talks to the notification daemon
+    // synchronously, so keep it off the request path
+    match templates.render(event, None) {
+        Ok(notification) => {
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = DesktopNotification::new()
+                    .summary(&notification.title)
+                    .body(&notification.plain_text())
+                    .icon("github")
+                    .show()
+                {
+                    warn!("Failed to show desktop notification: {}", e);
+                }
+            });
         }
+        Err(e) => warn!("Failed to render desktop notification: {:#}", e),
     }
+
+    Ok(enqueued)
 }
 
-fn verify_signature(payload: &str, signature: &str, secret: &str) -> anyhow::Result<()> {
-    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
-    mac.update(payload.as_bytes());
+/// Explains why no secret can verify requests. Secrets can expire while
+/// running, which is worth an error in the log, not just a 401.
+fn no_active_secret(secrets: &[WebhookSecret]) -> String {
+    let Some(last) = secrets
+        .iter()
+        .filter_map(|secret| Some((secret, secret.expires_at?)))
+        .max_by_key(|(_, expires_at)| *expires_at)
+    else {
+        return "Webhook secret is not configured".to_string();
+    };
+
+    let (secret, expires_at) = last;
+    let expired = OffsetDateTime::from_unix_timestamp(expires_at)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_else(|| expires_at.to_string());
+    let message = format!(
+        "All webhook secrets have expired, the last one ({}) at {}",
+        secret.name, expired
+    );
+    error!("{}, configure a current webhook secret", message);
+    message
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SignatureAlgorithm {
+    Sha256,
+    Sha1,
+}
+
+/// Checks the request's signature and returns the secret it was made with.
+fn verify_request<'a>(
+    headers: &HeaderMap,
+    body: &[u8],
+    secrets: &'a [WebhookSecret],
+    allow_sha1_signature: bool,
+) -> Result<&'a WebhookSecret, HandlerError> {
+    let mut active = secrets
+        .iter()
+        .enumerate()
+        .filter(|(_, secret)| !secret.is_expired())
+        .peekable();
+    if active.peek().is_none() {
+        return Err(HandlerError::AuthenticationError(no_active_secret(secrets)));
+    }
+
+    let header_value = |name: &str| headers.get(name).and_then(|sig| sig.to_str().ok());
 
+    // prefer sha256, only fall back to the legacy sha1 header when explicitly allowed
+    let legacy = header_value("X-Hub-Signature").filter(|_| allow_sha1_signature);
+    let (algorithm, signature) = if let Some(sig) = header_value("X-Hub-Signature-256") {
+        (
+            SignatureAlgorithm::Sha256,
+            sig.trim_start_matches("sha256="),
+        )
+    } else if let Some(sig) = legacy {
+        (SignatureAlgorithm::Sha1, sig.trim_start_matches("sha1="))
+    } else {
+        return Err(HandlerError::AuthenticationError(
+            "Missing signature header".into(),
+        ));
+    };
+
+    // secrets are tried in order; logging which one matched shows when a
+    // rotated-out secret stops being used
+    let mut last_error = None;
+    for (index, secret) in active {
+        match verify_signature(algorithm, body, signature, secret.secret.expose()) {
+            Ok(()) if index == 0 => {
+                debug!("Webhook signature matched secret {}", secret.name);
+                return Ok(secret);
+            }
+            Ok(()) => {
+                info!("Webhook signature matched secondary secret {}", secret.name);
+                return Ok(secret);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    let error = last_error.map_or_else(|| "Invalid signature".to_string(), |e| e.to_string());
+    Err(HandlerError::AuthenticationError(format!(
+        "Invalid signature: {}",
+        error
+    )))
+}
+
+fn verify_signature(
+    algorithm: SignatureAlgorithm,
+    payload: &[u8],
+    signature: &str,
+    secret: &str,
+) -> anyhow::Result<()> {
     let decoded_signature = hex::decode(signature)?;
-    mac.verify_slice(&decoded_signature)
-        .map_err(|_| anyhow::anyhow!("Invalid signature"))
+
+    match algorithm {
+        SignatureAlgorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())?;
+            mac.update(payload);
+            mac.verify_slice(&decoded_signature)
+        }
+        SignatureAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes())?;
+            mac.update(payload);
+            mac.verify_slice(&decoded_signature)
+        }
+    }
+    .map_err(|_| anyhow::anyhow!("Invalid signature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Secret;
+
+    const BODY: &[u8] = br#"{"action":"followed"}"#;
+
+    fn secret(name: &str, value: &str, expires_at: Option<i64>) -> WebhookSecret {
+        WebhookSecret {
+            name: name.to_string(),
+            secret: Secret::new(value),
+            expires_at,
+        }
+    }
+
+    fn signed(secret: &str, body: &[u8]) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Hub-Signature-256",
+            format!("sha256={}", signature).parse().unwrap(),
+        );
+        headers
+    }
+
+    fn signed_sha1(secret: &str, body: &[u8]) -> HeaderMap {
+        let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Hub-Signature",
+            format!("sha1={}", signature).parse().unwrap(),
+        );
+        headers
+    }
+
+    fn verified(headers: &HeaderMap, body: &[u8], allow_sha1: bool) -> bool {
+        let secrets = [secret("webhook_secret", "s3cret-value", None)];
+        verify_request(headers, body, &secrets, allow_sha1).is_ok()
+    }
+
+    #[test]
+    fn accepts_a_valid_sha256_signature() {
+        assert!(verified(&signed("s3cret-value", BODY), BODY, false));
+    }
+
+    #[test]
+    fn rejects_a_tampered_body() {
+        let headers = signed("s3cret-value", BODY);
+        assert!(!verified(&headers, br#"{"action":"unfollowed"}"#, false));
+    }
+
+    #[test]
+    fn rejects_a_wrong_secret_or_garbled_signature() {
+        assert!(!verified(&signed("other-secret", BODY), BODY, false));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Hub-Signature-256", "sha256=not-hex".parse().unwrap());
+        assert!(!verified(&headers, BODY, false));
+    }
+
+    #[test]
+    fn rejects_a_missing_signature() {
+        assert!(!verified(&HeaderMap::new(), BODY, false));
+        assert!(!verified(&HeaderMap::new(), BODY, true));
+    }
+
+    #[test]
+    fn sha1_signatures_only_when_allowed() {
+        let headers = signed_sha1("s3cret-value", BODY);
+        assert!(!verified(&headers, BODY, false));
+        assert!(verified(&headers, BODY, true));
+        assert!(!verified(&signed_sha1("other-secret", BODY), BODY, true));
+    }
+
+    #[test]
+    fn form_payloads_are_verified_raw_then_decoded() {
+        let json = r#"{"action":"followed","sender":{"login":"a b&c=d"}}"#;
+        let form = serde_urlencoded::to_string([("payload", json)]).unwrap();
+        let mut headers = signed("s3cret-value", form.as_bytes());
+        headers.insert(
+            CONTENT_TYPE,
+            "application/x-www-form-urlencoded".parse().unwrap(),
+        );
+
+        // GitHub signs the form body as sent, not the JSON inside it
+        assert!(verified(&headers, form.as_bytes(), false));
+        assert!(!verified(&headers, json.as_bytes(), false));
+
+        let payload = event_payload(&headers, Bytes::from(form)).unwrap();
+        assert_eq!(payload, json.as_bytes());
+    }
+
+    #[test]
+    fn json_payloads_pass_through_and_bad_forms_are_rejected() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        let payload = event_payload(&headers, Bytes::from_static(BODY)).unwrap();
+        assert_eq!(payload, BODY);
+
+        headers.insert(
+            CONTENT_TYPE,
+            "application/x-www-form-urlencoded; charset=utf-8"
+                .parse()
+                .unwrap(),
+        );
+        assert!(event_payload(&headers, Bytes::from_static(b"other=1")).is_err());
+    }
+
+    fn matched<'a>(headers: &HeaderMap, secrets: &'a [WebhookSecret]) -> Option<&'a str> {
+        verify_request(headers, BODY, secrets, false)
+            .ok()
+            .map(|secret| secret.name.as_str())
+    }
+
+    #[test]
+    fn rotation_accepts_the_old_secret_until_it_expires() {
+        let active = [
+            secret("current", "new-secret", None),
+            secret("previous", "old-secret", Some(i64::MAX)),
+        ];
+        assert_eq!(
+            matched(&signed("new-secret", BODY), &active),
+            Some("current")
+        );
+        assert_eq!(
+            matched(&signed("old-secret", BODY), &active),
+            Some("previous")
+        );
+
+        let expired = [
+            secret("current", "new-secret", None),
+            secret("previous", "old-secret", Some(0)),
+        ];
+        assert_eq!(
+            matched(&signed("new-secret", BODY), &expired),
+            Some("current")
+        );
+        assert_eq!(matched(&signed("old-secret", BODY), &expired), None);
+    }
+
+    #[test]
+    fn secrets_are_tried_in_order() {
+        let secrets = [
+            secret("first", "shared-secret", None),
+            secret("second", "shared-secret", None),
+        ];
+        assert_eq!(
+            matched(&signed("shared-secret", BODY), &secrets),
+            Some("first")
+        );
+
+        // an expired secret ahead of the list is skipped, not a dead end
+        let secrets = [
+            secret("expired", "shared-secret", Some(0)),
+            secret("second", "shared-secret", None),
+        ];
+        assert_eq!(
+            matched(&signed("shared-secret", BODY), &secrets),
+            Some("second")
+        );
+    }
+
+    #[test]
+    fn rejects_everything_once_all_secrets_expired() {
+        let secrets = [
+            secret("previous", "old-secret", Some(0)),
+            secret("current", "new-secret", Some(86_400)),
+        ];
+        let Err(HandlerError::AuthenticationError(message)) =
+            verify_request(&signed("old-secret", BODY), BODY, &secrets, false)
+        else {
+            panic!("a request signed with an expired secret was accepted");
+        };
+        assert_eq!(
+            message,
+            "All webhook secrets have expired, the last one (current) at 1970-01-02T00:00:00Z"
+        );
+    }
+
+    fn hook(config: serde_json::Value) -> Hook {
+        serde_json::from_value(serde_json::json!({
+            "type": "Repository",
+            "active": true,
+            "events": ["star"],
+            "config": config,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn disabled_ssl_verification_is_reported() {
+        let ok = hook(serde_json::json!({"content_type": "form", "insecure_ssl": "0"}));
+        assert!(hook_problems(&ok).is_empty());
+
+        for insecure_ssl in [serde_json::json!("1"), serde_json::json!(1)] {
+            let insecure = hook(serde_json::json!({"insecure_ssl": insecure_ssl}));
+            let problems = hook_problems(&insecure);
+            assert_eq!(problems.len(), 1);
+            assert!(problems[0].contains("SSL verification disabled"));
+        }
+    }
 }
@@ -1,8 +1,18 @@
 pub mod config;
 pub mod handlers;
+pub mod limits;
+pub mod metrics;
 pub mod models;
+pub mod outbox;
+pub mod poller;
+pub mod reload;
 pub mod services;
+pub mod state;
+pub mod storage;
+pub mod templates;
+pub mod util;
 
 pub use config::Config;
 pub use handlers::webhook::handle_webhook;
-pub use services::NotificationManager;
\ No newline at end of file
+pub use services::NotificationManager;
+pub use state::AppState;
\ No newline at end of file
@@ -0,0 +1,221 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use governor::{
+    clock::{Clock, DefaultClock},
+    state::keyed::DefaultKeyedStateStore,
+    state::{InMemoryState, NotKeyed},
+    NotUntil, Quota, RateLimiter,
+};
+use serde_json::json;
+use std::{
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU32,
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+use crate::config::LimitsConfig;
+
+/// How often idle per-IP buckets are dropped.
+const CLEANUP_INTERVAL_SECS: u64 = 60;
+
+type KeyedLimiter = RateLimiter<IpAddr, DefaultKeyedStateStore<IpAddr>, DefaultClock>;
+type DirectLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+/// Token buckets guarding `/webhook`, one per client IP plus one shared.
+pub struct InboundLimits {
+    clock: DefaultClock,
+    per_ip: Option<KeyedLimiter>,
+    global: Option<DirectLimiter>,
+    trust_forwarded_for: bool,
+}
+
+impl InboundLimits {
+    pub fn new(config: &LimitsConfig) -> Arc<Self> {
+        let clock = DefaultClock::default();
+        let per_ip = quota(
+            config.per_ip_per_minute,
+            config.per_ip_burst,
+            Quota::per_minute,
+        )
+        .map(|quota| RateLimiter::new(quota, DefaultKeyedStateStore::default(), &clock));
+        let global = quota(
+            config.global_per_second,
+            config.global_burst,
+            Quota::per_second,
+        )
+        .map(|quota| RateLimiter::direct_with_clock(quota, &clock));
+
+        let limits = Arc::new(Self {
+            clock,
+            per_ip,
+            global,
+            trust_forwarded_for: config.trust_forwarded_for,
+        });
+
+        if limits.per_ip.is_some() {
+            let weak = Arc::downgrade(&limits);
+            tokio::spawn(async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(CLEANUP_INTERVAL_SECS));
+                loop {
+                    interval.tick().await;
+                    let Some(limits) = weak.upgrade() else { break };
+                    if let Some(per_ip) = &limits.per_ip {
+                        per_ip.retain_recent();
+                    }
+                }
+            });
+        }
+
+        limits
+    }
+
+    /// Returns how long the caller should wait if the request is over a limit.
+    fn check(&self, ip: Option<IpAddr>) -> Result<(), Duration> {
+        if let (Some(limiter), Some(ip)) = (&self.per_ip, ip) {
+            limiter
+                .check_key(&ip)
+                .map_err(|not_until| self.wait_time(not_until))?;
+        }
+        if let Some(limiter) = &self.global {
+            limiter
+                .check()
+                .map_err(|not_until| self.wait_time(not_until))?;
+        }
+        Ok(())
+    }
+
+    fn wait_time(&self, not_until: NotUntil<<DefaultClock as Clock>::Instant>) -> Duration {
+        not_until.wait_time_from(self.clock.now())
+    }
+
+    fn client_ip(&self, request: &Request<Body>) -> Option<IpAddr> {
+        // the client controls everything left of what our proxy appended, so
+        // only the rightmost entry can be trusted
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| request.headers().get("X-Forwarded-For"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+
+        forwarded.or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip())
+        })
+    }
+}
+
+fn quota(rate: u32, burst: u32, per: fn(NonZeroU32) -> Quota) -> Option<Quota> {
+    let rate = NonZeroU32::new(rate)?;
+    Some(per(rate).allow_burst(NonZeroU32::new(burst).unwrap_or(rate)))
+}
+
+/// Rejects requests over the per-IP or global rate with a 429.
+pub async fn rate_limit(
+    State(limits): State<Arc<InboundLimits>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let ip = limits.client_ip(&request);
+    match limits.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            debug!(
+                "Rate limited webhook request from {}",
+                ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+            );
+            reject(StatusCode::TOO_MANY_REQUESTS, "Too many requests", wait)
+        }
+    }
+}
+
+/// Turns errors from the load-shedding and concurrency layers into a 503.
+pub async fn overloaded(error: BoxError) -> Response {
+    if error.is::<tower::load_shed::error::Overloaded>() {
+        warn!("Shedding webhook request, too many in flight");
+        return reject(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Service is overloaded",
+            Duration::from_secs(1),
+        );
+    }
+
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "error": format!("Unhandled error: {}", error) })),
+    )
+        .into_response()
+}
+
+fn reject(status: StatusCode, message: &str, retry_after: Duration) -> Response {
+    // Retry-After takes whole seconds, so round up to never invite an early retry
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    (
+        status,
+        [(RETRY_AFTER, seconds.max(1).to_string())],
+        Json(json!({ "error": message })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(trust_forwarded_for: bool) -> InboundLimits {
+        InboundLimits {
+            clock: DefaultClock::default(),
+            per_ip: None,
+            global: None,
+            trust_forwarded_for,
+        }
+    }
+
+    fn request(forwarded_for: &str) -> Request<Body> {
+        let mut request = Request::builder()
+            .header("X-Forwarded-For", forwarded_for)
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 443))));
+        request
+    }
+
+    #[test]
+    fn forwarded_for_uses_the_entry_the_proxy_appended() {
+        let limits = limits(true);
+        let proxy_seen = "203.0.113.7".parse::<IpAddr>().unwrap();
+
+        assert_eq!(limits.client_ip(&request("203.0.113.7")), Some(proxy_seen));
+        assert_eq!(
+            limits.client_ip(&request("1.2.3.4, 203.0.113.7")),
+            Some(proxy_seen)
+        );
+        assert_eq!(
+            limits.client_ip(&request("5.6.7.8, 9.9.9.9,203.0.113.7")),
+            Some(proxy_seen)
+        );
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_unless_trusted() {
+        let limits = limits(false);
+        assert_eq!(
+            limits.client_ip(&request("203.0.113.7")),
+            Some("10.0.0.1".parse().unwrap())
+        );
+    }
+}
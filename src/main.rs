@@ -1,8 +1,26 @@
 use anyhow::Result;
-use axum::{routing::post, Router};
+use arc_swap::ArcSwap;
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post},
+    Router,
+};
 use dotenv::dotenv;
-use github_notification_service::{handlers::webhook::handle_webhook, Config, NotificationManager};
-use std::{net::SocketAddr, sync::Arc};
+use github_notification_service::{
+    handlers::{admin, health, webhook::handle_webhook},
+    limits::{self, InboundLimits},
+    metrics::Metrics,
+    outbox::{DeliveryQueue, OutboxWorker},
+    poller::FollowerPoller,
+    reload::{ChannelSnapshot, ConfigReloader},
+    storage::Database,
+    templates::TemplateRenderer,
+    AppState, Config,
+};
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -19,19 +37,65 @@ async fn main() -> Result<()> {
         .init();
 
     let config = Config::new()?;
-    let manager = Arc::new(NotificationManager::new(&config)?);
+    let (queue, queued_events) = DeliveryQueue::new();
+    let db = Database::connect(&config.database_url).await?;
+    let channels = ChannelSnapshot::sync(&config, &db).await?;
+    let state = AppState {
+        manager: Arc::new(ArcSwap::from_pointee(channels.build_manager(&config)?)),
+        db,
+        outbox: config.outbox_config,
+        delivery_retention_secs: config.delivery_retention_secs,
+        queue,
+        templates: Arc::new(ArcSwap::from_pointee(TemplateRenderer::new(
+            config.template_dir.as_deref().map(Path::new),
+            &config.templates,
+            &channels.names(),
+        )?)),
+        health: Arc::default(),
+        metrics: Arc::new(Metrics::new()?),
+    };
 
-    //TODO: Implement the webhook handler
-    let app = Router::new()
-        .route("/webhook", post(handle_webhook))
-        .with_state(manager)
+    // picks up anything left over from before a restart as well as retries
+    OutboxWorker::new(state.clone(), queued_events).spawn();
+
+    if let Some(poller_config) = config.poller_config.clone() {
+        FollowerPoller::new(poller_config, state.clone()).spawn();
+    }
+
+    // outermost first: cheap rate checks, then shedding once the concurrency cap is full
+    let limits = InboundLimits::new(&config.limits);
+    let webhook = post(handle_webhook).layer(
+        ServiceBuilder::new()
+            .layer(middleware::from_fn_with_state(limits, limits::rate_limit))
+            .layer(HandleErrorLayer::new(limits::overloaded))
+            .load_shed()
+            .concurrency_limit(config.limits.max_concurrency)
+            .layer(DefaultBodyLimit::max(config.limits.max_body_bytes)),
+    );
+
+    let mut app = Router::new()
+        .route("/webhook", webhook)
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/status/channels", get(health::channels))
+        .route("/metrics", get(health::metrics));
+    match config.admin_token.clone() {
+        Some(token) => app = app.nest("/admin", admin::router(token.into())),
+        None => info!(
+            "ADMIN_TOKEN is not set, admin routes are disabled; channel status is still at /status/channels"
+        ),
+    }
+    let app = app
+        .with_state(state.clone())
         .layer(TraceLayer::new_for_http());
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.port.parse()?));
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    ConfigReloader::new(state, config, channels).spawn();
+
     info!("Server starting on {}", addr);
 
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
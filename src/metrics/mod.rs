@@ -0,0 +1,137 @@
+use anyhow::Result;
+use prometheus::{
+    histogram_opts, opts, Encoder, HistogramVec, IntCounterVec, IntGaugeVec, Registry, TextEncoder,
+};
+use std::time::Duration;
+
+use crate::storage::{Database, DeliveryStatus};
+
+/// Buckets for the webhook-to-delivery delay, which includes retries.
+const DELAY_BUCKETS: &[f64] = &[
+    0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0, 21600.0,
+];
+
+/// Prometheus metrics served on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    webhooks_received: IntCounterVec,
+    notifications: IntCounterVec,
+    send_duration: HistogramVec,
+    delivery_delay: HistogramVec,
+    outbox_depth: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new();
+
+        let webhooks_received = IntCounterVec::new(
+            opts!(
+                "webhooks_received_total",
+                "Webhook requests by GitHub event, action and signature check"
+            ),
+            &["event", "action", "signature"],
+        )?;
+        let notifications = IntCounterVec::new(
+            opts!(
+                "notifications_total",
+                "Delivery attempts by channel and result"
+            ),
+            &["channel", "result"],
+        )?;
+        let send_duration = HistogramVec::new(
+            histogram_opts!(
+                "notification_send_duration_seconds",
+                "Time spent sending one notification"
+            ),
+            &["channel"],
+        )?;
+        let delivery_delay = HistogramVec::new(
+            histogram_opts!(
+                "notification_delivery_delay_seconds",
+                "Time from receiving an event to delivering it",
+                DELAY_BUCKETS.to_vec()
+            ),
+            &["channel"],
+        )?;
+        let outbox_depth = IntGaugeVec::new(
+            opts!(
+                "outbox_deliveries",
+                "Deliveries waiting in the outbox by status"
+            ),
+            &["status"],
+        )?;
+
+        registry.register(Box::new(webhooks_received.clone()))?;
+        registry.register(Box::new(notifications.clone()))?;
+        registry.register(Box::new(send_duration.clone()))?;
+        registry.register(Box::new(delivery_delay.clone()))?;
+        registry.register(Box::new(outbox_depth.clone()))?;
+
+        Ok(Self {
+            registry,
+            webhooks_received,
+            notifications,
+            send_duration,
+            delivery_delay,
+            outbox_depth,
+        })
+    }
+
+    pub fn record_webhook(&self, event: &str, action: &str, signature_valid: bool) {
+        let signature = if signature_valid { "valid" } else { "invalid" };
+        self.webhooks_received
+            .with_label_values(&[event, action, signature])
+            .inc();
+    }
+
+    /// `delay` is the time since the event was received, known for successes.
+    pub fn record_delivery(
+        &self,
+        channel: &str,
+        success: bool,
+        latency: Duration,
+        delay: Option<Duration>,
+    ) {
+        let result = if success { "sent" } else { "failed" };
+        self.notifications
+            .with_label_values(&[channel, result])
+            .inc();
+        self.send_duration
+            .with_label_values(&[channel])
+            .observe(latency.as_secs_f64());
+        if let Some(delay) = delay {
+            self.delivery_delay
+                .with_label_values(&[channel])
+                .observe(delay.as_secs_f64());
+        }
+    }
+
+    pub fn record_rate_limited(&self, channel: &str) {
+        self.notifications
+            .with_label_values(&[channel, "rate_limited"])
+            .inc();
+    }
+
+    /// Refreshes the outbox gauges and encodes everything in text format.
+    pub async fn render(&self, db: &Database) -> Result<String> {
+        let depth = db.outbox_depth().await?;
+        for status in [
+            DeliveryStatus::Pending,
+            DeliveryStatus::InFlight,
+            DeliveryStatus::Failed,
+        ] {
+            let count = depth
+                .iter()
+                .find(|(name, _)| name == status.as_str())
+                .map_or(0, |(_, count)| *count);
+            self.outbox_depth
+                .with_label_values(&[status.as_str()])
+                .set(count);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
@@ -1,12 +1,16 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct FollowerEvent {
     pub action: String,
     pub sender: Sender,
+    /// Unix timestamp of when the follow was first observed. Only known for
+    /// events synthesized from follower snapshots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub followed_at: Option<u64>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Sender {
     pub login: String,
     pub avatar_url: String,
@@ -34,14 +38,106 @@ pub struct TelegramMessage {
     pub parse_mode: String,
 }
 
+#[derive(Serialize)]
+pub struct TelegramPhoto {
+    pub chat_id: String,
+    pub photo: String,
+    pub caption: String,
+    pub parse_mode: String,
+}
+
 #[derive(Serialize)]
 pub struct DiscordMessage {
-    pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
     pub username: String,
     pub avatar_url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub embeds: Vec<DiscordEmbed>,
+    pub allowed_mentions: DiscordAllowedMentions,
+}
+
+/// Which mentions Discord may turn into pings. The default, empty list
+/// disables them all.
+#[derive(Serialize, Default)]
+pub struct DiscordAllowedMentions {
+    pub parse: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct DiscordEmbed {
+    pub title: String,
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub color: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<DiscordEmbedAuthor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<DiscordEmbedImage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<DiscordEmbedField>,
+}
+
+#[derive(Serialize)]
+pub struct DiscordEmbedAuthor {
+    pub name: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DiscordEmbedImage {
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct DiscordEmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
 }
 
 #[derive(Serialize)]
 pub struct SlackMessage {
+    /// Fallback shown in notifications and by clients without Block Kit.
+    pub text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<SlackBlock>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SlackBlock {
+    Section {
+        text: SlackText,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        accessory: Option<SlackAccessory>,
+    },
+    Context {
+        elements: Vec<SlackText>,
+    },
+}
+
+#[derive(Serialize)]
+pub struct SlackText {
+    #[serde(rename = "type")]
+    pub text_type: String,
     pub text: String,
 }
+
+impl SlackText {
+    pub fn mrkdwn(text: impl Into<String>) -> Self {
+        Self {
+            text_type: "mrkdwn".to_string(),
+            text: text.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SlackAccessory {
+    Image { image_url: String, alt_text: String },
+}
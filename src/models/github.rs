@@ -0,0 +1,445 @@
+use serde::{Deserialize, Serialize};
+
+use super::{FollowerEvent, Sender};
+
+/// A webhook GitHub sends, picked by its `X-GitHub-Event` header. Only the
+/// fields we announce are modelled; the rest of the payload is ignored.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GitHubEvent {
+    /// Follows have no webhook of their own; requests without an event
+    /// header and the follower poller produce these.
+    Follower(FollowerEvent),
+    Star(StarEvent),
+    Fork(ForkEvent),
+    Watch(WatchEvent),
+    Issues(IssuesEvent),
+    PullRequest(PullRequestEvent),
+    Release(ReleaseEvent),
+    Sponsorship(SponsorshipEvent),
+    Public(PublicEvent),
+    Member(MemberEvent),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Repository {
+    pub full_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub stargazers_count: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct StarEvent {
+    /// `created` or `deleted`.
+    pub action: String,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ForkEvent {
+    /// The newly created fork.
+    pub forkee: Repository,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WatchEvent {
+    /// Always `started`; GitHub sends it alongside `star`.
+    pub action: String,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    pub user: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct IssuesEvent {
+    pub action: String,
+    pub issue: Issue,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub merged: bool,
+    pub user: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PullRequestEvent {
+    pub action: String,
+    pub pull_request: PullRequest,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub html_url: String,
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ReleaseEvent {
+    pub action: String,
+    pub release: Release,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SponsorshipTier {
+    pub name: String,
+    #[serde(default)]
+    pub monthly_price_in_dollars: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Sponsorship {
+    pub sponsor: Sender,
+    pub tier: SponsorshipTier,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SponsorshipEvent {
+    pub action: String,
+    pub sponsorship: Sponsorship,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PublicEvent {
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MemberEvent {
+    pub action: String,
+    pub member: Sender,
+    pub repository: Repository,
+    pub sender: Sender,
+}
+
+/// Event names we understand, as sent in `X-GitHub-Event`.
+pub const GITHUB_EVENTS: &[&str] = &[
+    "star",
+    "fork",
+    "watch",
+    "issues",
+    "pull_request",
+    "release",
+    "sponsorship",
+    "public",
+    "member",
+];
+
+impl GitHubEvent {
+    /// Parses a webhook body according to its `X-GitHub-Event` header. A
+    /// missing header means a follower event; an event we don't handle
+    /// gives `None`.
+    pub fn parse(event: Option<&str>, body: &[u8]) -> serde_json::Result<Option<Self>> {
+        let Some(event) = event else {
+            return serde_json::from_slice(body).map(|event| Some(GitHubEvent::Follower(event)));
+        };
+        if !GITHUB_EVENTS.contains(&event) {
+            return Ok(None);
+        }
+
+        // tag the payload so serde picks the variant
+        let mut payload: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(body)?;
+        payload.insert("event".to_string(), event.into());
+        serde_json::from_value(payload.into()).map(Some)
+    }
+
+    /// Decodes an event stored by the outbox. Rows written before other
+    /// events were supported hold a bare follower event.
+    pub fn from_stored(payload: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(payload)
+            .or_else(|_| serde_json::from_str(payload).map(GitHubEvent::Follower))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GitHubEvent::Follower(_) => "follower",
+            GitHubEvent::Star(_) => "star",
+            GitHubEvent::Fork(_) => "fork",
+            GitHubEvent::Watch(_) => "watch",
+            GitHubEvent::Issues(_) => "issues",
+            GitHubEvent::PullRequest(_) => "pull_request",
+            GitHubEvent::Release(_) => "release",
+            GitHubEvent::Sponsorship(_) => "sponsorship",
+            GitHubEvent::Public(_) => "public",
+            GitHubEvent::Member(_) => "member",
+        }
+    }
+
+    pub fn action(&self) -> Option<&str> {
+        match self {
+            GitHubEvent::Follower(e) => Some(&e.action),
+            GitHubEvent::Star(e) => Some(&e.action),
+            GitHubEvent::Watch(e) => Some(&e.action),
+            GitHubEvent::Issues(e) => Some(&e.action),
+            GitHubEvent::PullRequest(e) => Some(&e.action),
+            GitHubEvent::Release(e) => Some(&e.action),
+            GitHubEvent::Sponsorship(e) => Some(&e.action),
+            GitHubEvent::Member(e) => Some(&e.action),
+            GitHubEvent::Fork(_) | GitHubEvent::Public(_) => None,
+        }
+    }
+
+    /// Templates are named after this, and it is stored as the event type.
+    /// Follower events keep their action so `followed` and `unfollowed`
+    /// stay separate.
+    pub fn key(&self) -> &str {
+        match self {
+            GitHubEvent::Follower(e) => &e.action,
+            _ => self.name(),
+        }
+    }
+
+    pub fn sender(&self) -> &Sender {
+        match self {
+            GitHubEvent::Follower(e) => &e.sender,
+            GitHubEvent::Star(e) => &e.sender,
+            GitHubEvent::Fork(e) => &e.sender,
+            GitHubEvent::Watch(e) => &e.sender,
+            GitHubEvent::Issues(e) => &e.sender,
+            GitHubEvent::PullRequest(e) => &e.sender,
+            GitHubEvent::Release(e) => &e.sender,
+            GitHubEvent::Sponsorship(e) => &e.sender,
+            GitHubEvent::Public(e) => &e.sender,
+            GitHubEvent::Member(e) => &e.sender,
+        }
+    }
+
+    /// Whether this action is worth a notification. The rest (labels,
+    /// edits, review requests, ...) are acknowledged and dropped.
+    pub fn is_announced(&self) -> bool {
+        match self {
+            GitHubEvent::Follower(_) | GitHubEvent::Fork(_) | GitHubEvent::Public(_) => true,
+            GitHubEvent::Star(e) => e.action == "created",
+            GitHubEvent::Watch(e) => e.action == "started",
+            GitHubEvent::Issues(e) => matches!(e.action.as_str(), "opened" | "closed" | "reopened"),
+            GitHubEvent::PullRequest(e) => {
+                matches!(e.action.as_str(), "opened" | "closed" | "reopened")
+            }
+            GitHubEvent::Release(e) => e.action == "published",
+            GitHubEvent::Sponsorship(e) => {
+                matches!(e.action.as_str(), "created" | "cancelled" | "tier_changed")
+            }
+            GitHubEvent::Member(e) => matches!(e.action.as_str(), "added" | "removed"),
+        }
+    }
+
+    /// The page a notification about this event should link to.
+    pub fn link(&self) -> &str {
+        match self {
+            GitHubEvent::Follower(e) => &e.sender.html_url,
+            GitHubEvent::Star(e) => &e.repository.html_url,
+            GitHubEvent::Fork(e) => &e.forkee.html_url,
+            GitHubEvent::Watch(e) => &e.repository.html_url,
+            GitHubEvent::Issues(e) => &e.issue.html_url,
+            GitHubEvent::PullRequest(e) => &e.pull_request.html_url,
+            GitHubEvent::Release(e) => &e.release.html_url,
+            GitHubEvent::Sponsorship(e) => &e.sponsorship.sponsor.html_url,
+            GitHubEvent::Public(e) => &e.repository.html_url,
+            GitHubEvent::Member(e) => &e.repository.html_url,
+        }
+    }
+
+    /// Unix timestamp of the original follow, for unfollow notices.
+    pub fn followed_at(&self) -> Option<u64> {
+        match self {
+            GitHubEvent::Follower(e) => e.followed_at,
+            _ => None,
+        }
+    }
+}
+
+impl From<FollowerEvent> for GitHubEvent {
+    fn from(event: FollowerEvent) -> Self {
+        GitHubEvent::Follower(event)
+    }
+}
+
+/// Sent once when a webhook is created, and again from its settings page.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PingEvent {
+    pub zen: String,
+    pub hook_id: i64,
+    pub hook: Hook,
+    #[serde(default)]
+    pub repository: Option<Repository>,
+    #[serde(default)]
+    pub organization: Option<Organization>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Hook {
+    /// `Repository`, `Organization` or `App`.
+    #[serde(rename = "type")]
+    pub hook_type: String,
+    #[serde(default)]
+    pub active: bool,
+    pub events: Vec<String>,
+    pub config: HookConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HookConfig {
+    /// `json` or `form`.
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// `"1"` when GitHub skips TLS certificate checks for this hook.
+    #[serde(default)]
+    pub insecure_ssl: Option<serde_json::Value>,
+}
+
+impl HookConfig {
+    pub fn skips_tls_verification(&self) -> bool {
+        match &self.insecure_ssl {
+            Some(serde_json::Value::String(value)) => value == "1",
+            Some(serde_json::Value::Number(value)) => value.as_u64() == Some(1),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Organization {
+    pub login: String,
+}
+
+impl PingEvent {
+    /// The repository or organization the hook belongs to, if any.
+    pub fn target(&self) -> Option<&str> {
+        self.repository
+            .as_ref()
+            .map(|repository| repository.full_name.as_str())
+            .or(self.organization.as_ref().map(|org| org.login.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(event: &str, name: &str) -> Option<GitHubEvent> {
+        let body = match name {
+            "star_created" => include_str!("fixtures/star_created.json"),
+            "star_deleted" => include_str!("fixtures/star_deleted.json"),
+            "issues_opened" => include_str!("fixtures/issues_opened.json"),
+            "issues_labeled" => include_str!("fixtures/issues_labeled.json"),
+            "pull_request_closed" => include_str!("fixtures/pull_request_closed.json"),
+            "release_published" => include_str!("fixtures/release_published.json"),
+            "fork" => include_str!("fixtures/fork.json"),
+            "label_created" => include_str!("fixtures/label_created.json"),
+            _ => panic!("no fixture {}", name),
+        };
+        GitHubEvent::parse(Some(event), body.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn the_header_picks_the_variant() {
+        let Some(GitHubEvent::Star(star)) = fixture("star", "star_created") else {
+            panic!("star payload did not parse as a star");
+        };
+        assert_eq!(star.action, "created");
+        assert_eq!(star.repository.full_name, "Codertocat/Hello-World");
+        assert_eq!(star.repository.stargazers_count, Some(1));
+
+        let Some(GitHubEvent::PullRequest(pr)) = fixture("pull_request", "pull_request_closed")
+        else {
+            panic!("pull_request payload did not parse as a pull request");
+        };
+        assert!(pr.pull_request.merged);
+        assert_eq!(pr.pull_request.number, 2);
+
+        let Some(GitHubEvent::Fork(fork)) = fixture("fork", "fork") else {
+            panic!("fork payload did not parse as a fork");
+        };
+        assert_eq!(fork.forkee.full_name, "Octocoders/Hello-World");
+
+        let release = fixture("release", "release_published").unwrap();
+        assert_eq!(release.key(), "release");
+        assert_eq!(release.sender().login, "Codertocat");
+        assert_eq!(
+            release.link(),
+            "https://github.com/Codertocat/Hello-World/releases/tag/0.0.1"
+        );
+    }
+
+    #[test]
+    fn the_payload_must_match_the_header() {
+        // the injected tag wins, and a star payload has no issue in it
+        let body = include_str!("fixtures/star_created.json");
+        assert!(GitHubEvent::parse(Some("issues"), body.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn unknown_events_are_not_parsed() {
+        assert!(fixture("label", "label_created").is_none());
+        assert!(GitHubEvent::parse(Some("label"), b"not even json")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn without_a_header_the_body_is_a_follower_event() {
+        let body = br#"{"action":"followed","sender":{"login":"octocat","avatar_url":"a","html_url":"h"}}"#;
+        let event = GitHubEvent::parse(None, body).unwrap().unwrap();
+        assert_eq!(event.name(), "follower");
+        assert_eq!(event.key(), "followed");
+    }
+
+    #[test]
+    fn routine_actions_are_not_announced() {
+        assert!(fixture("star", "star_created").unwrap().is_announced());
+        assert!(!fixture("star", "star_deleted").unwrap().is_announced());
+        assert!(fixture("issues", "issues_opened").unwrap().is_announced());
+        assert!(!fixture("issues", "issues_labeled").unwrap().is_announced());
+        assert!(fixture("pull_request", "pull_request_closed")
+            .unwrap()
+            .is_announced());
+        assert!(fixture("fork", "fork").unwrap().is_announced());
+    }
+
+    #[test]
+    fn stored_events_round_trip_with_their_tag() {
+        let event = fixture("issues", "issues_opened").unwrap();
+        let stored = serde_json::to_string(&event).unwrap();
+        assert!(stored.contains(r#""event":"issues""#));
+
+        let restored = GitHubEvent::from_stored(&stored).unwrap();
+        assert_eq!(restored.key(), "issues");
+        assert_eq!(restored.link(), event.link());
+
+        // rows from before other events were supported hold a bare follower event
+        let legacy = r#"{"action":"unfollowed","sender":{"login":"octocat","avatar_url":"a","html_url":"h"}}"#;
+        assert_eq!(
+            GitHubEvent::from_stored(legacy).unwrap().key(),
+            "unfollowed"
+        );
+    }
+}
@@ -1,2 +1,6 @@
 pub mod events;
-pub use events::*;
\ No newline at end of file
+pub mod github;
+pub mod notification;
+pub use events::*;
+pub use github::*;
+pub use notification::*;
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// Channel-agnostic notification. Each service renders it in its own native
+/// format; `plain_text` is the fallback for channels without rich formatting.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    /// Where the notification should point, e.g. the follower's profile.
+    pub link: Option<String>,
+    /// Thumbnail to show next to the notification, e.g. an avatar.
+    pub image: Option<String>,
+    pub actor: Option<Actor>,
+    pub fields: Vec<NotificationField>,
+}
+
+/// The GitHub user who triggered the notification.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Actor {
+    pub login: String,
+    pub html_url: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct NotificationField {
+    pub name: String,
+    pub value: String,
+}
+
+impl Notification {
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.push(NotificationField {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Body followed by one `name: value` line per field.
+    pub fn plain_text(&self) -> String {
+        let mut text = self.body.clone();
+        for field in &self.fields {
+            text.push_str(&format!("\n{}: {}", field.name, field.value));
+        }
+        text
+    }
+}
@@ -0,0 +1,395 @@
+use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
+use rand::Rng;
+use std::{collections::BTreeMap, time::Duration};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{error, info, warn};
+
+use crate::{
+    config::OutboxConfig,
+    models::GitHubEvent,
+    services::{ChannelResult, DeliveryReport},
+    state::AppState,
+    storage::PendingNotification,
+    util::unix_now,
+};
+
+const BATCH_SIZE: i64 = 50;
+const QUEUE_CAPACITY: usize = 1024;
+/// How long a claimed delivery is hidden from other workers.
+const LEASE_SECS: u64 = 300;
+
+#[derive(Debug, Default)]
+pub struct DeliveryOutcome {
+    pub sent: usize,
+    pub failed: usize,
+    pub postponed: usize,
+}
+
+/// Hands freshly enqueued events to the worker so they go out without waiting
+/// for the next poll. Losing a wake-up is harmless: the rows are persisted and
+/// the periodic sweep picks them up.
+#[derive(Clone)]
+pub struct DeliveryQueue {
+    sender: mpsc::Sender<i64>,
+}
+
+impl DeliveryQueue {
+    pub fn new() -> (Self, mpsc::Receiver<i64>) {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        (Self { sender }, receiver)
+    }
+
+    pub fn schedule(&self, event_id: i64) {
+        if let Err(e) = self.sender.try_send(event_id) {
+            warn!(
+                "Delivery queue unavailable ({}), event {} will be sent on the next sweep",
+                e, event_id
+            );
+        }
+    }
+}
+
+/// Background task that delivers queued events and retries whatever is left
+/// in `v_pending_notifications`.
+pub struct OutboxWorker {
+    state: AppState,
+    queue: mpsc::Receiver<i64>,
+}
+
+impl OutboxWorker {
+    pub fn new(state: AppState, queue: mpsc::Receiver<i64>) -> Self {
+        Self { state, queue }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    async fn run(mut self) {
+        let poll_interval = Duration::from_secs(self.state.outbox.poll_interval_secs.max(1));
+        info!("Outbox worker polling every {:?}", poll_interval);
+
+        let mut sweep = tokio::time::interval(poll_interval);
+        loop {
+            tokio::select! {
+                Some(event_id) = self.queue.recv() => {
+                    // leases keep this from racing the sweep over the same rows
+                    let state = self.state.clone();
+                    tokio::spawn(async move {
+                        log_outcome(deliver_due(&state, Some(event_id)).await);
+                    });
+                }
+                _ = sweep.tick() => log_outcome(deliver_due(&self.state, None).await),
+            }
+        }
+    }
+}
+
+fn log_outcome(result: Result<DeliveryOutcome>) {
+    match result {
+        Ok(outcome) if outcome.sent + outcome.failed + outcome.postponed > 0 => info!(
+            "Outbox run finished: {} sent, {} failed, {} postponed",
+            outcome.sent, outcome.failed, outcome.postponed
+        ),
+        Ok(_) => {}
+        Err(e) => error!("Outbox run failed: {:#}", e),
+    }
+}
+
+/// Attempts every delivery that is due, optionally only those of one event.
+pub async fn deliver_due(state: &AppState, event_id: Option<i64>) -> Result<DeliveryOutcome> {
+    let mut outcome = DeliveryOutcome::default();
+
+    loop {
+        let batch = state.db.due_notifications(event_id, BATCH_SIZE).await?;
+        let batch_len = batch.len() as i64;
+
+        // claim everything first, then fan out per event so each event is rendered once
+        let mut claimed: BTreeMap<i64, Vec<PendingNotification>> = BTreeMap::new();
+        for pending in batch {
+            if state.db.claim(pending.notification_id, LEASE_SECS).await? {
+                claimed.entry(pending.event_id).or_default().push(pending);
+            }
+        }
+
+        let reports = join_all(
+            claimed
+                .into_iter()
+                .map(|(event_id, rows)| deliver_event(state, event_id, rows)),
+        )
+        .await;
+        for report in reports {
+            let report = report?;
+            outcome.sent += report.succeeded();
+            outcome.failed += report.failed();
+            outcome.postponed += report.postponed();
+        }
+
+        if batch_len < BATCH_SIZE {
+            return Ok(outcome);
+        }
+    }
+}
+
+/// Sends the claimed deliveries of one event concurrently and records each result.
+async fn deliver_event(
+    state: &AppState,
+    event_id: i64,
+    rows: Vec<PendingNotification>,
+) -> Result<DeliveryReport> {
+    let event = rows
+        .first()
+        .and_then(|row| row.payload.as_deref())
+        .ok_or_else(|| anyhow!("Event {} has no stored payload", event_id))
+        .and_then(|payload| {
+            GitHubEvent::from_stored(payload).context("Failed to decode stored event")
+        });
+
+    let report = match event {
+        Ok(event) => {
+            let channels: Vec<&str> = rows.iter().map(|row| row.service_type.as_str()).collect();
+            let templates = state.templates.load_full();
+            state
+                .manager
+                .load_full()
+                .notify_channels(&channels, |channel| templates.render(&event, Some(channel)))
+                .await
+        }
+        Err(e) => DeliveryReport {
+            results: rows
+                .iter()
+                .map(|row| ChannelResult {
+                    channel: row.service_type.clone(),
+                    success: false,
+                    error: Some(format!("{:#}", e)),
+                    retry_after: None,
+                    latency: Duration::ZERO,
+                })
+                .collect(),
+        },
+    };
+
+    for (row, result) in rows.iter().zip(&report.results) {
+        record_result(state, row, result).await?;
+    }
+    state.db.complete_event(event_id).await?;
+
+    Ok(report)
+}
+
+async fn record_result(
+    state: &AppState,
+    pending: &PendingNotification,
+    result: &ChannelResult,
+) -> Result<()> {
+    if let Some(retry_after) = result.retry_after {
+        // round up so the retry never lands before the provider's window opens
+        let retry_in = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        state.metrics.record_rate_limited(&pending.service_type);
+        info!(
+            "{} delivery {} rate limited, retrying in {}s",
+            pending.service_type, pending.notification_id, retry_in
+        );
+        return state
+            .db
+            .postpone(pending.notification_id, retry_in.max(1))
+            .await;
+    }
+
+    let delay = pending
+        .received_at
+        .filter(|_| result.success)
+        .map(|received_at| {
+            Duration::from_secs(unix_now().saturating_sub(received_at.max(0) as u64))
+        });
+    state
+        .metrics
+        .record_delivery(&pending.service_type, result.success, result.latency, delay);
+
+    let Some(error) = result.error.as_deref().filter(|_| !result.success) else {
+        state.health.record_success(&pending.service_type);
+        return state.db.mark_sent(pending.notification_id).await;
+    };
+    state.health.record_failure(&pending.service_type, error);
+
+    let attempts = pending.attempts as u32 + 1;
+    if attempts >= state.outbox.max_attempts {
+        error!(
+            "Giving up on {} delivery {} after {} attempts: {}",
+            pending.service_type, pending.notification_id, attempts, error
+        );
+        state.db.mark_dead(pending.notification_id, error).await
+    } else {
+        let delay = backoff(&state.outbox, attempts);
+        warn!(
+            "{} delivery {} failed (attempt {}), retrying in {}s: {}",
+            pending.service_type, pending.notification_id, attempts, delay, error
+        );
+        state
+            .db
+            .mark_failed(pending.notification_id, error, delay)
+            .await
+    }
+}
+
+/// Exponential backoff in seconds with "equal jitter": somewhere between half
+/// and all of the capped exponential delay.
+fn backoff(config: &OutboxConfig, attempt: u32) -> u64 {
+    let exponential = config
+        .base_backoff_secs
+        .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)))
+        .min(config.max_backoff_secs)
+        .max(1);
+
+    rand::thread_rng()
+        .gen_range(exponential / 2..=exponential)
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        models::{FollowerEvent, Notification, Sender},
+        services::{ChannelKind, ChannelSettings, NotificationManager, NotificationService},
+        storage::{Database, DeliveryRecord},
+    };
+    use std::sync::Arc;
+
+    struct FailingService;
+
+    #[async_trait::async_trait]
+    impl NotificationService for FailingService {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn kind(&self) -> ChannelKind {
+            ChannelKind::Slack
+        }
+
+        async fn send(&self, _notification: &Notification) -> Result<()> {
+            Err(anyhow!("provider is down"))
+        }
+    }
+
+    /// State with one channel, `flaky`, that fails every send, and an event
+    /// queued for it.
+    async fn failing_delivery(name: &str) -> (AppState, i64, std::path::PathBuf) {
+        let (db, path) = Database::temporary(&format!("outbox-{}", name)).await;
+        let (mut state, _) = AppState::for_tests(db);
+        state.outbox.base_backoff_secs = 1;
+
+        let mut manager = NotificationManager::new(&Config::for_tests(), &[]).unwrap();
+        manager
+            .register(Box::new(FailingService), ChannelSettings::default())
+            .unwrap();
+        state.manager.store(Arc::new(manager));
+
+        let event = GitHubEvent::from(FollowerEvent {
+            action: "followed".to_string(),
+            sender: Sender {
+                login: "octocat".to_string(),
+                avatar_url: String::new(),
+                html_url: String::new(),
+            },
+            followed_at: None,
+        });
+        let event_id = state
+            .db
+            .enqueue_event(&event, &["flaky".to_string()])
+            .await
+            .unwrap();
+        (state, event_id, path)
+    }
+
+    async fn delivery(state: &AppState, event_id: i64) -> DeliveryRecord {
+        state
+            .db
+            .deliveries_of(&[event_id])
+            .await
+            .unwrap()
+            .pop()
+            .unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_with_equal_jitter_up_to_the_cap() {
+        let config = OutboxConfig {
+            base_backoff_secs: 30,
+            max_backoff_secs: 3600,
+            ..OutboxConfig::default()
+        };
+        for _ in 0..200 {
+            assert!((15..=30).contains(&backoff(&config, 1)));
+            assert!((60..=120).contains(&backoff(&config, 3)));
+            assert!((1800..=3600).contains(&backoff(&config, 10)));
+            assert!((1800..=3600).contains(&backoff(&config, u32::MAX)));
+        }
+
+        let tiny = OutboxConfig {
+            base_backoff_secs: 0,
+            ..config
+        };
+        assert_eq!(backoff(&tiny, 1), 1);
+    }
+
+    #[tokio::test]
+    async fn failures_are_retried_until_max_attempts_then_dead() {
+        let (mut state, event_id, path) = failing_delivery("dead").await;
+        state.outbox.max_attempts = 2;
+
+        let outcome = deliver_due(&state, None).await.unwrap();
+        assert_eq!((outcome.sent, outcome.failed), (0, 1));
+        let first = delivery(&state, event_id).await;
+        assert_eq!((first.status.as_str(), first.attempts), ("failed", 1));
+        assert_eq!(first.error_message.as_deref(), Some("provider is down"));
+
+        assert!(first.next_attempt_at.is_some());
+
+        // a base of 1s backs off for 1s, timestamps have whole seconds
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        let outcome = deliver_due(&state, None).await.unwrap();
+        assert_eq!(outcome.failed, 1);
+        let last = delivery(&state, event_id).await;
+        assert_eq!((last.status.as_str(), last.attempts), ("dead", 2));
+        assert!(last.next_attempt_at.is_none());
+
+        let event = state.db.event(event_id).await.unwrap().unwrap();
+        assert!(event.processed);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn leases_hide_claimed_rows_until_they_expire() {
+        let (state, event_id, path) = failing_delivery("lease").await;
+        let notification_id = delivery(&state, event_id).await.id;
+
+        // a worker that died holding an expired lease
+        assert!(state.db.claim(notification_id, 0).await.unwrap());
+        assert_eq!(delivery(&state, event_id).await.status, "in_flight");
+
+        // the row is due again, and a live lease keeps others away
+        assert!(state.db.claim(notification_id, LEASE_SECS).await.unwrap());
+        assert!(!state.db.claim(notification_id, LEASE_SECS).await.unwrap());
+        assert!(state
+            .db
+            .due_notifications(None, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the stale worker's outcome no longer applies once the row is settled
+        state.db.mark_sent(notification_id).await.unwrap();
+        state
+            .db
+            .mark_failed(notification_id, "late failure", 60)
+            .await
+            .unwrap();
+        let record = delivery(&state, event_id).await;
+        assert_eq!((record.status.as_str(), record.attempts), ("sent", 2));
+        let _ = std::fs::remove_file(path);
+    }
+}
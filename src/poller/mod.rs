@@ -0,0 +1,532 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{
+    header::{ACCEPT, ETAG, IF_NONE_MATCH, USER_AGENT},
+    Client, StatusCode,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::{scrub, PollerConfig},
+    handlers::webhook::process_event,
+    models::{FollowerEvent, GitHubEvent, Sender},
+    state::AppState,
+    util::unix_now,
+};
+use snapshot::{CachedPage, FollowerRecord, FollowerSnapshot};
+
+pub mod snapshot;
+
+const PER_PAGE: usize = 100;
+
+/// Periodically diffs the authenticated user's followers against the last
+/// snapshot, since GitHub has no webhook for new followers.
+pub struct FollowerPoller {
+    client: Client,
+    config: PollerConfig,
+    state: AppState,
+}
+
+enum PageResponse {
+    NotModified,
+    Modified {
+        etag: Option<String>,
+        followers: Vec<Sender>,
+    },
+}
+
+impl FollowerPoller {
+    pub fn new(config: PollerConfig, state: AppState) -> Self {
+        Self {
+            client: Client::new(),
+            config,
+            state,
+        }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    async fn run(self) {
+        info!(
+            "Polling followers of {} every {}s",
+            self.config.login, self.config.interval_secs
+        );
+
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.poll_once().await {
+                error!("Follower poll failed: {}", scrub(&format!("{:#}", e)));
+            }
+        }
+    }
+
+    pub async fn poll_once(&self) -> Result<()> {
+        let path = Path::new(&self.config.snapshot_path);
+        let previous = FollowerSnapshot::load(path).await?;
+        let cached_pages = previous
+            .as_ref()
+            .map(|snapshot| snapshot.pages.as_slice())
+            .unwrap_or_default();
+
+        let mut pages = Vec::new();
+        let mut fetched: HashMap<String, Sender> = HashMap::new();
+        let mut changed = false;
+
+        for page in 1.. {
+            let cached = cached_pages.get(page - 1);
+            let last_page = match self
+                .fetch_page(page, cached.and_then(|c| c.etag.as_deref()))
+                .await?
+            {
+                PageResponse::NotModified => {
+                    let cached = cached
+                        .cloned()
+                        .ok_or_else(|| anyhow!("GitHub returned 304 for uncached page {}", page))?;
+                    let last_page = cached.logins.len() < PER_PAGE;
+                    pages.push(cached);
+                    last_page
+                }
+                PageResponse::Modified { etag, followers } => {
+                    changed = true;
+                    let last_page = followers.len() < PER_PAGE;
+                    pages.push(CachedPage {
+                        etag,
+                        logins: followers.iter().map(|f| f.login.clone()).collect(),
+                    });
+                    fetched.extend(followers.into_iter().map(|f| (f.login.clone(), f)));
+                    last_page
+                }
+            };
+
+            if last_page {
+                break;
+            }
+        }
+
+        let Some(mut snapshot) = previous else {
+            // first run: record who already follows us without announcing anyone,
+            // since when is unknown
+            let snapshot = FollowerSnapshot {
+                followers: fetched
+                    .into_values()
+                    .map(|f| {
+                        (
+                            f.login,
+                            FollowerRecord {
+                                avatar_url: f.avatar_url,
+                                html_url: f.html_url,
+                                followed_at: None,
+                            },
+                        )
+                    })
+                    .collect(),
+                pages,
+            };
+            snapshot.save(path).await?;
+            info!(
+                "Recorded baseline of {} followers",
+                snapshot.followers.len()
+            );
+            return Ok(());
+        };
+
+        if !changed && pages.len() == snapshot.pages.len() {
+            debug!("Follower list unchanged");
+            return Ok(());
+        }
+
+        let current: HashSet<&String> = pages.iter().flat_map(|p| p.logins.iter()).collect();
+        let new_followers: Vec<Sender> = current
+            .iter()
+            .filter(|login| !snapshot.followers.contains_key(login.as_str()))
+            .filter_map(|login| fetched.get(login.as_str()).cloned())
+            .collect();
+
+        let lost_followers: Vec<(String, FollowerRecord)> = snapshot
+            .followers
+            .iter()
+            .filter(|(login, _)| !current.contains(login))
+            .map(|(login, record)| (login.clone(), record.clone()))
+            .collect();
+
+        // only changes that were handed to the outbox go into the snapshot, so
+        // the next poll finds the others again
+        let mut undelivered = 0;
+        for (login, record) in lost_followers {
+            let event = FollowerEvent {
+                action: "unfollowed".to_string(),
+                sender: Sender {
+                    login: login.clone(),
+                    avatar_url: record.avatar_url,
+                    html_url: record.html_url,
+                },
+                followed_at: record.followed_at,
+            };
+            if self.dispatch(event).await {
+                snapshot.followers.remove(&login);
+            } else {
+                undelivered += 1;
+            }
+        }
+
+        let now = unix_now();
+        for sender in new_followers {
+            let record = FollowerRecord {
+                avatar_url: sender.avatar_url.clone(),
+                html_url: sender.html_url.clone(),
+                followed_at: Some(now),
+            };
+            let login = sender.login.clone();
+            let event = FollowerEvent {
+                action: "followed".to_string(),
+                sender,
+                followed_at: Some(now),
+            };
+            if self.dispatch(event).await {
+                snapshot.followers.insert(login, record);
+            } else {
+                undelivered += 1;
+            }
+        }
+
+        if undelivered == 0 {
+            snapshot.pages = pages;
+        } else {
+            // fresh ETags would answer the retry with 304s
+            warn!(
+                "{} follower changes could not be queued, retrying them on the next poll",
+                undelivered
+            );
+        }
+        snapshot.save(path).await
+    }
+
+    /// Hands the event to the outbox. Returns `false` if it could not be queued.
+    async fn dispatch(&self, event: FollowerEvent) -> bool {
+        let event = GitHubEvent::from(event);
+        match process_event(&self.state, &event, None).await {
+            Ok(_) => true,
+            Err(e) => {
+                warn!(
+                    "Failed to notify about {} {}: {:?}",
+                    event.key(),
+                    event.sender().login,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    async fn fetch_page(&self, page: usize, etag: Option<&str>) -> Result<PageResponse> {
+        let url = format!(
+            "{}/users/{}/followers",
+            self.config.api_base_url.trim_end_matches('/'),
+            self.config.login
+        );
+
+        let mut request = self
+            .client
+            .get(&url)
+            .query(&[("per_page", PER_PAGE), ("page", page)])
+            .bearer_auth(self.config.github_token.expose())
+            .header(ACCEPT, "application/vnd.github+json")
+            .header(USER_AGENT, "github-notification-service")
+            .header("X-GitHub-Api-Version", "2022-11-28");
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        let response = request
+            .send()
+            .await
+            .context("Failed to fetch followers from GitHub")?;
+
+        if let Some(remaining) = response.headers().get("x-ratelimit-remaining") {
+            debug!("GitHub rate limit remaining: {:?}", remaining);
+        }
+
+        match response.status() {
+            StatusCode::NOT_MODIFIED => Ok(PageResponse::NotModified),
+            status if status.is_success() => {
+                let etag = response
+                    .headers()
+                    .get(ETAG)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string);
+                let followers = response
+                    .json()
+                    .await
+                    .context("Failed to parse followers response")?;
+                Ok(PageResponse::Modified { etag, followers })
+            }
+            status => {
+                let body = response.text().await.unwrap_or_default();
+                Err(anyhow!("GitHub API error {}: {}", status, body))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Secret,
+        storage::{Database, HistoryFilter, Page},
+    };
+    use axum::{
+        extract::{Query, State},
+        http::HeaderMap,
+        response::{IntoResponse, Response},
+        routing::get,
+        Json, Router,
+    };
+    use serde_json::json;
+    use std::{
+        net::TcpListener,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    /// Stands in for the GitHub followers endpoint, with one ETag per list.
+    #[derive(Default)]
+    struct MockGitHub {
+        followers: Vec<&'static str>,
+        version: u32,
+        /// Answers every request with this status when set.
+        failing: Option<StatusCode>,
+        /// `If-None-Match` of every request, in order.
+        requests: Vec<Option<String>>,
+    }
+
+    type Mock = Arc<Mutex<MockGitHub>>;
+
+    async fn serve_followers(
+        State(mock): State<Mock>,
+        Query(query): Query<HashMap<String, String>>,
+        headers: HeaderMap,
+    ) -> Response {
+        let mut mock = mock.lock().unwrap();
+        let if_none_match = headers
+            .get(IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        mock.requests.push(if_none_match.clone());
+        if let Some(status) = mock.failing {
+            return (status, "upstream trouble").into_response();
+        }
+
+        assert_eq!(query.get("page").map(String::as_str), Some("1"));
+        let etag = format!("\"v{}\"", mock.version);
+        if if_none_match.as_deref() == Some(etag.as_str()) {
+            return StatusCode::NOT_MODIFIED.into_response();
+        }
+        let page: Vec<_> = mock
+            .followers
+            .iter()
+            .map(|login| {
+                json!({
+                    "login": login,
+                    "avatar_url": format!("https://avatars.example/{}", login),
+                    "html_url": format!("https://github.com/{}", login),
+                })
+            })
+            .collect();
+        ([(ETAG, etag)], Json(page)).into_response()
+    }
+
+    impl MockGitHub {
+        fn set_followers(&mut self, followers: &[&'static str]) {
+            self.followers = followers.to_vec();
+            self.version += 1;
+        }
+    }
+
+    struct Harness {
+        mock: Mock,
+        poller: FollowerPoller,
+        snapshot_path: PathBuf,
+        db_path: PathBuf,
+        _queue: tokio::sync::mpsc::Receiver<i64>,
+    }
+
+    impl Harness {
+        async fn new(name: &str, followers: &[&'static str]) -> Self {
+            let mock = Mock::default();
+            mock.lock().unwrap().set_followers(followers);
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let api_base_url = format!("http://{}", listener.local_addr().unwrap());
+            let app = Router::new()
+                .route("/users/:login/followers", get(serve_followers))
+                .with_state(mock.clone());
+            tokio::spawn(
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service()),
+            );
+
+            let (db, db_path) = Database::temporary(&format!("poller-{}", name)).await;
+            let (state, queue) = AppState::for_tests(db);
+            let snapshot_path = std::env::temp_dir().join(format!(
+                "github-notification-service-{}-{}.json",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&snapshot_path);
+            let config = PollerConfig {
+                github_token: Secret::new("test-github-token"),
+                login: "octocat".to_string(),
+                api_base_url,
+                interval_secs: 60,
+                snapshot_path: snapshot_path.display().to_string(),
+            };
+
+            Self {
+                mock,
+                poller: FollowerPoller::new(config, state),
+                snapshot_path,
+                db_path,
+                _queue: queue,
+            }
+        }
+
+        async fn snapshot(&self) -> FollowerSnapshot {
+            FollowerSnapshot::load(&self.snapshot_path)
+                .await
+                .unwrap()
+                .unwrap()
+        }
+
+        /// `(event_type, login)` of every recorded event, oldest first.
+        async fn events(&self) -> Vec<(String, String)> {
+            let page = Page {
+                number: 1,
+                size: 100,
+            };
+            let (events, _) = self
+                .poller
+                .state
+                .db
+                .events(&HistoryFilter::default(), page)
+                .await
+                .unwrap();
+            events
+                .into_iter()
+                .rev()
+                .map(|event| (event.event_type, event.sender_login))
+                .collect()
+        }
+
+        fn cleanup(self) {
+            let _ = std::fs::remove_file(&self.snapshot_path);
+            let _ = std::fs::remove_file(&self.db_path);
+        }
+    }
+
+    fn pairs(events: &[(&str, &str)]) -> Vec<(String, String)> {
+        events
+            .iter()
+            .map(|(event, login)| (event.to_string(), login.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn first_poll_records_a_baseline_without_announcing_anyone() {
+        let harness = Harness::new("baseline", &["alice", "bob"]).await;
+        harness.poller.poll_once().await.unwrap();
+
+        let snapshot = harness.snapshot().await;
+        assert_eq!(
+            snapshot.followers.keys().collect::<Vec<_>>(),
+            ["alice", "bob"]
+        );
+        // nobody knows since when they follow
+        assert!(snapshot.followers.values().all(|f| f.followed_at.is_none()));
+        assert_eq!(snapshot.pages[0].etag.as_deref(), Some("\"v1\""));
+        assert!(harness.events().await.is_empty());
+        harness.cleanup();
+    }
+
+    #[tokio::test]
+    async fn new_and_lost_followers_are_announced() {
+        let harness = Harness::new("diff", &["alice", "bob"]).await;
+        harness.poller.poll_once().await.unwrap();
+
+        harness
+            .mock
+            .lock()
+            .unwrap()
+            .set_followers(&["bob", "carol"]);
+        harness.poller.poll_once().await.unwrap();
+
+        assert_eq!(
+            harness.events().await,
+            pairs(&[("unfollowed", "alice"), ("followed", "carol")])
+        );
+        let snapshot = harness.snapshot().await;
+        assert_eq!(
+            snapshot.followers.keys().collect::<Vec<_>>(),
+            ["bob", "carol"]
+        );
+        assert!(snapshot.followers["bob"].followed_at.is_none());
+        assert!(snapshot.followers["carol"].followed_at.is_some());
+        assert_eq!(snapshot.pages[0].etag.as_deref(), Some("\"v2\""));
+        harness.cleanup();
+    }
+
+    #[tokio::test]
+    async fn unchanged_pages_are_revalidated_with_the_etag() {
+        let harness = Harness::new("etag", &["alice"]).await;
+        harness.poller.poll_once().await.unwrap();
+        harness.poller.poll_once().await.unwrap();
+
+        assert_eq!(
+            harness.mock.lock().unwrap().requests,
+            [None, Some("\"v1\"".to_string())]
+        );
+        assert!(harness.events().await.is_empty());
+        assert_eq!(
+            harness
+                .snapshot()
+                .await
+                .followers
+                .keys()
+                .collect::<Vec<_>>(),
+            ["alice"]
+        );
+        harness.cleanup();
+    }
+
+    #[tokio::test]
+    async fn a_failed_poll_is_retried_from_the_same_snapshot() {
+        let harness = Harness::new("retry", &["alice"]).await;
+        harness.poller.poll_once().await.unwrap();
+
+        {
+            let mut mock = harness.mock.lock().unwrap();
+            mock.set_followers(&["alice", "bob"]);
+            mock.failing = Some(StatusCode::BAD_GATEWAY);
+        }
+        let error = harness.poller.poll_once().await.unwrap_err();
+        assert!(error.to_string().contains("502"));
+        assert!(harness.events().await.is_empty());
+        assert_eq!(
+            harness.snapshot().await.pages[0].etag.as_deref(),
+            Some("\"v1\"")
+        );
+
+        harness.mock.lock().unwrap().failing = None;
+        harness.poller.poll_once().await.unwrap();
+        assert_eq!(harness.events().await, pairs(&[("followed", "bob")]));
+        harness.cleanup();
+    }
+}
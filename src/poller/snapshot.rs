@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// What we last saw on the followers endpoint, persisted between polls.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct FollowerSnapshot {
+    pub followers: BTreeMap<String, FollowerRecord>,
+    pub pages: Vec<CachedPage>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FollowerRecord {
+    pub avatar_url: String,
+    pub html_url: String,
+    /// Unix timestamp of the poll that first saw this follower. Unknown for
+    /// followers recorded in the first-run baseline.
+    #[serde(default)]
+    pub followed_at: Option<u64>,
+}
+
+/// A single page of the followers list together with the ETag it was served with,
+/// so unchanged pages can be answered with `304 Not Modified`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CachedPage {
+    pub etag: Option<String>,
+    pub logins: Vec<String>,
+}
+
+impl FollowerSnapshot {
+    /// Returns `None` when no snapshot has been written yet.
+    pub async fn load(path: &Path) -> Result<Option<Self>> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => {
+                let snapshot = serde_json::from_slice(&bytes).with_context(|| {
+                    format!("Failed to parse follower snapshot {}", path.display())
+                })?;
+                Ok(Some(snapshot))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read follower snapshot {}", path.display())),
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        // write to a temporary file first so a crash never leaves a truncated snapshot
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write follower snapshot {}", tmp_path.display()))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .with_context(|| format!("Failed to replace follower snapshot {}", path.display()))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{scrub, Config},
+    services::NotificationManager,
+    state::AppState,
+    storage::{ConfigCipher, Database, ServiceConfig, ServiceConfigRow},
+    templates::TemplateRenderer,
+};
+
+/// How often the config file and `service_configs` are checked for changes.
+const WATCH_INTERVAL_SECS: u64 = 2;
+
+/// The channels in `service_configs`, as stored and as decoded.
+pub struct ChannelSnapshot {
+    rows: Vec<ServiceConfigRow>,
+    channels: BTreeMap<String, ServiceConfig>,
+}
+
+impl ChannelSnapshot {
+    /// Stores the channels declared in `config`, then reads back the table.
+    pub async fn sync(config: &Config, db: &Database) -> Result<Self> {
+        let cipher = cipher(config)?;
+        db.sync_service_configs(&config.channels, cipher.as_ref())
+            .await?;
+        Self::read(config, db).await
+    }
+
+    pub async fn read(config: &Config, db: &Database) -> Result<Self> {
+        let cipher = cipher(config)?;
+        let rows = db.service_config_rows().await?;
+        let channels = rows
+            .iter()
+            .map(|row| {
+                let declared = config.channels.get(&row.name);
+                Ok((row.name.clone(), row.decode(cipher.as_ref(), declared)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rows, channels })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.channels.keys().map(String::as_str).collect()
+    }
+
+    pub fn build_manager(&self, config: &Config) -> Result<NotificationManager> {
+        let channels: Vec<ServiceConfig> = self.channels.values().cloned().collect();
+        NotificationManager::new(config, &channels)
+    }
+}
+
+fn cipher(config: &Config) -> Result<Option<ConfigCipher>> {
+    config
+        .service_config_key
+        .as_ref()
+        .map(|key| ConfigCipher::new(key.expose()))
+        .transpose()
+}
+
+/// Rebuilds the channel registry on SIGHUP, when the config file changes or
+/// when `service_configs` is edited; the first two also reload templates.
+/// Settings bound at startup (port, database, outbox, poller, admin token) still need a
+/// restart.
+pub struct ConfigReloader {
+    state: AppState,
+    config: Config,
+    channels: ChannelSnapshot,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigReloader {
+    pub fn new(state: AppState, config: Config, channels: ChannelSnapshot) -> Self {
+        let modified = config.config_file.as_deref().and_then(modified_at);
+        Self {
+            state,
+            config,
+            channels,
+            modified,
+        }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    async fn run(mut self) {
+        let mut hangup = match Hangup::new() {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!(
+                    "Failed to listen for SIGHUP, config reload is disabled: {}",
+                    e
+                );
+                return;
+            }
+        };
+        let mut watch = tokio::time::interval(Duration::from_secs(WATCH_INTERVAL_SECS));
+
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    info!("Received SIGHUP, reloading configuration");
+                    self.reload_config().await;
+                }
+                _ = watch.tick() => {
+                    let modified = self.config.config_file.as_deref().and_then(modified_at);
+                    if modified != self.modified {
+                        self.modified = modified;
+                        info!("Config file changed, reloading configuration");
+                        self.reload_config().await;
+                    } else {
+                        self.check_service_configs().await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Re-reads the config file and env vars, stores their channels and swaps
+    /// in the result. A config that fails to load or validate leaves the
+    /// running one untouched.
+    async fn reload_config(&mut self) {
+        let loaded = async {
+            let config = Config::new()?;
+            // templates are checked before anything is stored; channels added
+            // at runtime are still in the current snapshot
+            let mut names = self.channels.names();
+            names.extend(config.channels.keys().map(String::as_str));
+            let templates = TemplateRenderer::new(
+                config.template_dir.as_deref().map(Path::new),
+                &config.templates,
+                &names,
+            )?;
+            let channels = ChannelSnapshot::sync(&config, &self.state.db).await?;
+            let manager = channels.build_manager(&config)?;
+            Ok::<_, anyhow::Error>((config, templates, channels, manager))
+        }
+        .await;
+
+        match loaded {
+            Ok((config, templates, channels, manager)) => {
+                self.state.templates.store(Arc::new(templates));
+                self.swap(manager, channels);
+                self.config = config;
+            }
+            Err(e) => error!(
+                "Config reload failed, keeping the current configuration: {}",
+                scrub(&format!("{:#}", e))
+            ),
+        }
+    }
+
+    /// Picks up channels that were enabled, disabled or edited in the table.
+    async fn check_service_configs(&mut self) {
+        let rows = match self.state.db.service_config_rows().await {
+            Ok(rows) => rows,
+            Err(e) => {
+                warn!("Failed to check service_configs for changes: {:#}", e);
+                return;
+            }
+        };
+        if rows == self.channels.rows {
+            return;
+        }
+
+        info!("service_configs changed, reloading channels");
+        let loaded = async {
+            let channels = ChannelSnapshot::read(&self.config, &self.state.db).await?;
+            let manager = channels.build_manager(&self.config)?;
+            Ok::<_, anyhow::Error>((channels, manager))
+        }
+        .await;
+
+        match loaded {
+            Ok((channels, manager)) => self.swap(manager, channels),
+            Err(e) => {
+                error!(
+                    "Channel reload failed, keeping the current channels: {}",
+                    scrub(&format!("{:#}", e))
+                );
+                // don't retry the same broken rows on every tick
+                self.channels.rows = rows;
+            }
+        }
+    }
+
+    fn swap(&mut self, manager: NotificationManager, channels: ChannelSnapshot) {
+        // requests already holding the old manager finish with it
+        self.state.manager.store(Arc::new(manager));
+
+        let diff = ChannelDiff::between(&self.channels.channels, &channels.channels);
+        if diff.is_empty() {
+            info!("Configuration reloaded, channels unchanged");
+        } else {
+            info!(
+                "Configuration reloaded: added [{}], removed [{}], changed [{}]",
+                diff.added.join(", "),
+                diff.removed.join(", "),
+                diff.changed.join(", ")
+            );
+        }
+        self.channels = channels;
+    }
+}
+
+/// Names of the channels that differ between two configurations.
+#[derive(Debug, Default)]
+struct ChannelDiff<'a> {
+    added: Vec<&'a str>,
+    removed: Vec<&'a str>,
+    changed: Vec<&'a str>,
+}
+
+impl<'a> ChannelDiff<'a> {
+    fn between(
+        old: &'a BTreeMap<String, ServiceConfig>,
+        new: &'a BTreeMap<String, ServiceConfig>,
+    ) -> Self {
+        let mut diff = Self::default();
+        for (name, channel) in new {
+            match old.get(name) {
+                None => diff.added.push(name),
+                Some(previous) if previous != channel => diff.changed.push(name),
+                Some(_) => {}
+            }
+        }
+        diff.removed = old
+            .keys()
+            .filter(|name| !new.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        diff
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+#[cfg(unix)]
+struct Hangup(tokio::signal::unix::Signal);
+
+#[cfg(unix)]
+impl Hangup {
+    fn new() -> Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Self(signal(SignalKind::hangup())?))
+    }
+
+    async fn recv(&mut self) {
+        self.0.recv().await;
+    }
+}
+
+// there is no SIGHUP elsewhere, so only file changes trigger a reload
+#[cfg(not(unix))]
+struct Hangup;
+
+#[cfg(not(unix))]
+impl Hangup {
+    fn new() -> Result<Self> {
+        Ok(Self)
+    }
+
+    async fn recv(&mut self) {
+        std::future::pending::<()>().await
+    }
+}
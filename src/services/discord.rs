@@ -1,9 +1,18 @@
-use crate::models::DiscordMessage;
+use crate::models::{
+    DiscordAllowedMentions, DiscordEmbed, DiscordEmbedAuthor, DiscordEmbedField, DiscordEmbedImage, DiscordMessage,
+    Notification,
+};
 use anyhow::{Result, Context};
 use reqwest::Client;
-use tracing::{info, error};
+use tracing::info;
+
+use super::{markup::Markup, ratelimit::check_response};
+
+/// GitHub's dark grey, used as the embed's accent stripe.
+const EMBED_COLOR: u32 = 0x24292e;
 
 pub struct DiscordService {
+    name: String,
     client: Client,
     webhook_url: String,
 }
@@ -11,22 +20,82 @@ pub struct DiscordService {
 impl DiscordService {
     pub fn new(webhook_url: String) -> Self {
         Self {
+            name: "discord".to_string(),
             client: Client::new(),
             webhook_url,
         }
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl super::NotificationService for DiscordService {
-    async fn send(&self, title: &str, message: &str) -> Result<()> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> super::ChannelKind {
+        super::ChannelKind::Discord
+    }
+
+    /// Fetching a webhook returns its details without posting anything.
+    async fn health_check(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(&self.webhook_url)
+            .send()
+            .await
+            .context("Failed to reach Discord")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!(
+                "Discord webhook lookup returned {}",
+                response.status()
+            ));
+        }
+        Ok(())
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let markup = Markup::DiscordMarkdown;
+        let embed = DiscordEmbed {
+            title: markup.escape(&notification.title),
+            description: markup.escape(&notification.body),
+            url: notification.link.clone(),
+            color: EMBED_COLOR,
+            author: notification.actor.as_ref().map(|actor| DiscordEmbedAuthor {
+                name: actor.login.clone(),
+                url: actor.html_url.clone(),
+                icon_url: actor.avatar_url.clone(),
+            }),
+            thumbnail: notification
+                .image
+                .as_ref()
+                .map(|url| DiscordEmbedImage { url: url.clone() }),
+            fields: notification
+                .fields
+                .iter()
+                .map(|field| DiscordEmbedField {
+                    name: markup.escape(&field.name),
+                    value: markup.escape(&field.value),
+                    inline: true,
+                })
+                .collect(),
+        };
+
         let payload = DiscordMessage {
-            content: format!("**{}**\n{}", title, message),
+            content: None,
             username: "GitHub Follower Bot".to_string(),
             avatar_url: Some(
                 "https://github.githubassets.com/images/modules/logos_page/GitHub-Mark.png"
                     .to_string()
             ),
+            embeds: vec![embed],
+            allowed_mentions: DiscordAllowedMentions::default(),
         };
 
         let response = self.client
@@ -36,10 +105,7 @@ impl super::NotificationService for DiscordService {
             .await
             .context("Failed to send Discord message")?;
 
-        if !response.status().is_success() {
-            error!("Discord API error: {:?}", response.text().await?);
-            return Err(anyhow::anyhow!("Discord API error"));
-        }
+        check_response(response, "Discord").await?;
 
         info!("Discord notification sent successfully");
         Ok(())
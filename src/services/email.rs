@@ -1,11 +1,18 @@
+use crate::models::Notification;
 use anyhow::Result;
 use lettre::{
-    message::Message, transport::smtp::authentication::Credentials, SmtpTransport, Transport,
+    message::{Message, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
 };
 use tracing::{error, info};
 
+use super::markup::Markup;
+use crate::config::scrub;
+
 pub struct EmailService {
-    mailer: SmtpTransport,
+    name: String,
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
     from_email: String,
     to_email: String,
 }
@@ -20,36 +27,114 @@ impl EmailService {
     ) -> Result<Self> {
         let creds = Credentials::new(smtp_username, smtp_password);
 
-        let mailer = SmtpTransport::relay(&smtp_server)?
+        // async transport so a slow SMTP server never blocks the runtime
+        let mailer = AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp_server)?
             .credentials(creds)
             .build();
 
         Ok(Self {
+            name: "email".to_string(),
             mailer,
             from_email,
             to_email,
         })
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl super::NotificationService for EmailService {
-    async fn send(&self, title: &str, message: &str) -> Result<()> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> super::ChannelKind {
+        super::ChannelKind::Email
+    }
+
+    /// Connects and issues a NOOP to the SMTP server.
+    async fn health_check(&self) -> Result<()> {
+        if self.mailer.test_connection().await? {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("SMTP server did not answer NOOP"))
+        }
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
         let email = Message::builder()
             .from(self.from_email.parse()?)
             .to(self.to_email.parse()?)
-            .subject(title)
-            .body(message.to_string())?;
+            .subject(&notification.title)
+            .multipart(MultiPart::alternative_plain_html(
+                plain_body(notification),
+                html_body(notification),
+            ))?;
 
-        match self.mailer.send(&email) {
+        match self.mailer.send(email).await {
             Ok(_) => {
                 info!("Email notification sent successfully");
                 Ok(())
             }
             Err(e) => {
-                error!("Failed to send email: {}", e);
+                error!("Failed to send email: {}", scrub(&e.to_string()));
                 Err(e.into())
             }
         }
     }
 }
+
+fn plain_body(notification: &Notification) -> String {
+    let mut text = notification.plain_text();
+    if let Some(link) = notification
+        .link
+        .as_ref()
+        .filter(|link| !text.contains(*link))
+    {
+        text.push_str(&format!("\n\n{}", link));
+    }
+    text
+}
+
+fn html_body(notification: &Notification) -> String {
+    let escape = |text: &str| Markup::Html.escape(text);
+    let mut html = String::from("<div style=\"font-family: sans-serif\">");
+
+    if let Some(image) = &notification.image {
+        html.push_str(&format!(
+            "<img src=\"{}\" alt=\"\" width=\"64\" height=\"64\" style=\"border-radius: 50%\">",
+            escape(image)
+        ));
+    }
+    html.push_str(&format!("<h2>{}</h2>", escape(&notification.title)));
+    html.push_str(&format!(
+        "<p>{}</p>",
+        escape(&notification.body).replace('\n', "<br>")
+    ));
+
+    if !notification.fields.is_empty() {
+        html.push_str("<table>");
+        for field in &notification.fields {
+            html.push_str(&format!(
+                "<tr><th align=\"left\">{}</th><td>{}</td></tr>",
+                escape(&field.name),
+                escape(&field.value)
+            ));
+        }
+        html.push_str("</table>");
+    }
+
+    if let Some(link) = &notification.link {
+        html.push_str(&format!(
+            "<p><a href=\"{}\">View on GitHub</a></p>",
+            escape(link)
+        ));
+    }
+
+    html.push_str("</div>");
+    html
+}
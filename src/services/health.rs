@@ -0,0 +1,46 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::util::unix_now;
+
+/// Delivery track record of one channel. Times are unix seconds.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChannelStatus {
+    pub last_success_at: Option<u64>,
+    pub last_failure_at: Option<u64>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+/// Outcomes of recent deliveries per channel name. Lives outside
+/// `NotificationManager` so a config reload doesn't wipe it.
+#[derive(Debug, Default)]
+pub struct ChannelHealth {
+    channels: Mutex<BTreeMap<String, ChannelStatus>>,
+}
+
+impl ChannelHealth {
+    pub fn record_success(&self, channel: &str) {
+        let mut channels = self.channels.lock().unwrap();
+        let status = channels.entry(channel.to_string()).or_default();
+        status.last_success_at = Some(unix_now());
+        status.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&self, channel: &str, error: &str) {
+        let mut channels = self.channels.lock().unwrap();
+        let status = channels.entry(channel.to_string()).or_default();
+        status.last_failure_at = Some(unix_now());
+        status.last_error = Some(error.to_string());
+        status.consecutive_failures += 1;
+    }
+
+    pub fn status(&self, channel: &str) -> ChannelStatus {
+        self.channels
+            .lock()
+            .unwrap()
+            .get(channel)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,104 @@
+/// Markup dialects spoken by the notification channels. Notifications carry
+/// plain text; each service escapes whatever it interpolates into its markup
+/// so user-controlled text (logins, bios, titles) can never change formatting
+/// or break the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    Plain,
+    /// Telegram `parse_mode: "HTML"`.
+    TelegramHtml,
+    /// Slack mrkdwn, which only needs the `&`, `<` and `>` entities.
+    SlackMrkdwn,
+    /// Discord markdown, with `@everyone`/`@here` defused.
+    DiscordMarkdown,
+    /// HTML text and attribute values, as used in email bodies.
+    Html,
+}
+
+impl Markup {
+    pub fn escape(self, text: &str) -> String {
+        match self {
+            Markup::Plain => text.to_string(),
+            Markup::TelegramHtml => replace_entities(text, false),
+            Markup::SlackMrkdwn => replace_entities(text, false),
+            Markup::DiscordMarkdown => {
+                // a zero-width space keeps mass mentions from pinging anyone
+                backslash_escape(text, "\\*_~`|>#-[]()<:")
+                    .replace("@everyone", "@\u{200b}everyone")
+                    .replace("@here", "@\u{200b}here")
+            }
+            Markup::Html => replace_entities(text, true),
+        }
+    }
+}
+
+fn backslash_escape(text: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn replace_entities(text: &str, quotes: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if quotes => escaped.push_str("&quot;"),
+            '\'' if quotes => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_is_left_alone() {
+        assert_eq!(
+            Markup::Plain.escape("<b>*x*</b> & @everyone"),
+            "<b>*x*</b> & @everyone"
+        );
+    }
+
+    #[test]
+    fn telegram_html_escapes_tags_and_ampersands() {
+        assert_eq!(
+            Markup::TelegramHtml.escape("<b>bold</b> & \"quoted\""),
+            "&lt;b&gt;bold&lt;/b&gt; &amp; \"quoted\""
+        );
+    }
+
+    #[test]
+    fn slack_mrkdwn_escapes_control_characters() {
+        assert_eq!(
+            Markup::SlackMrkdwn.escape("<!channel> & <https://x|y>"),
+            "&lt;!channel&gt; &amp; &lt;https://x|y&gt;"
+        );
+    }
+
+    #[test]
+    fn discord_markdown_escapes_formatting_and_defuses_mentions() {
+        assert_eq!(
+            Markup::DiscordMarkdown.escape("**bold** _it_ [link](x) @everyone @here"),
+            "\\*\\*bold\\*\\* \\_it\\_ \\[link\\]\\(x\\) @\u{200b}everyone @\u{200b}here"
+        );
+    }
+
+    #[test]
+    fn html_escapes_quotes_too() {
+        assert_eq!(
+            Markup::Html.escape("<a href=\"x\">it's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;it&#39;s&lt;/a&gt;"
+        );
+    }
+}
@@ -1,142 +1,419 @@
-use anyhow::Result;
+use crate::{
+    config::{scrub, ChannelConfig, WebhookSecret},
+    models::Notification,
+    storage::ServiceConfig,
+};
+use anyhow::{anyhow, bail, Result};
 use discord::DiscordService;
 use email::EmailService;
+use futures::future::join_all;
+use ratelimit::{ChannelLimiter, RateLimited};
+use serde::{Deserialize, Serialize};
 use slack::SlackService;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
 use telegram::TelegramService;
+use tracing::debug;
 use whatsapp::WhatsAppService;
 
 pub mod discord;
 pub mod email;
+pub mod health;
+pub mod markup;
+pub mod ratelimit;
 pub mod slack;
 pub mod telegram;
 pub mod whatsapp;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelKind {
+    WhatsApp,
+    Telegram,
+    Discord,
+    Slack,
+    Email,
+}
+
+impl ChannelKind {
+    pub const ALL: [ChannelKind; 5] = [
+        ChannelKind::WhatsApp,
+        ChannelKind::Telegram,
+        ChannelKind::Discord,
+        ChannelKind::Slack,
+        ChannelKind::Email,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChannelKind::WhatsApp => "whatsapp",
+            ChannelKind::Telegram => "telegram",
+            ChannelKind::Discord => "discord",
+            ChannelKind::Slack => "slack",
+            ChannelKind::Email => "email",
+        }
+    }
+
+    /// Sending rate each provider tolerates for one destination, as
+    /// `(messages per minute, burst)`.
+    pub fn default_rate_limit(&self) -> Option<(u32, u32)> {
+        match self {
+            // one message per second per chat
+            ChannelKind::Telegram => Some((60, 1)),
+            // webhooks get 5 requests per 2 seconds and 30 per minute
+            ChannelKind::Discord => Some((30, 5)),
+            ChannelKind::Slack => Some((60, 1)),
+            // the pair limit allows one message to a number every 6 seconds
+            ChannelKind::WhatsApp => Some((10, 1)),
+            ChannelKind::Email => None,
+        }
+    }
+}
+
+impl fmt::Display for ChannelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[async_trait::async_trait]
 pub trait NotificationService: Send + Sync {
-    async fn send(&self, title: &str, message: &str) -> Result<()>;
+    /// Unique name of this channel instance, e.g. `slack` or `slack-ops`.
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> ChannelKind;
+
+    async fn send(&self, notification: &Notification) -> Result<()>;
+
+    /// Checks that the channel is usable without sending anything. Channels
+    /// without an active probe report healthy.
+    async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
-pub struct NotificationManager {
-    pub webhook_secret: String,
-    whatsapp: Option<WhatsAppService>,
-    telegram: Option<TelegramService>,
-    discord: Option<DiscordService>,
-    slack: Option<SlackService>,
-    email: Option<EmailService>,
+#[derive(Debug, Clone)]
+pub struct ChannelSettings {
+    pub notify_unfollows: bool,
+    pub timeout: Duration,
+    /// Overrides the kind's default sending rate; 0 means unlimited.
+    pub rate_limit_per_minute: Option<u32>,
+    pub rate_limit_burst: Option<u32>,
 }
 
-impl NotificationManager {
-    pub fn new(config: &crate::config::Config) -> Result<Self> {
-        // init services based on available config
-        let whatsapp = if let Some(whatsapp_config) = &config.whatsapp_config {
-            Some(WhatsAppService::new(
-                whatsapp_config.api_key.clone(),
-                whatsapp_config.phone_number.clone(),
-            ))
-        } else {
-            None
-        };
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self {
+            notify_unfollows: false,
+            timeout: Duration::from_secs(10),
+            rate_limit_per_minute: None,
+            rate_limit_burst: None,
+        }
+    }
+}
 
-        let telegram = if let Some(telegram_config) = &config.telegram_config {
-            Some(TelegramService::new(
-                telegram_config.bot_token.clone(),
-                telegram_config.chat_id.clone(),
-            ))
-        } else {
-            None
-        };
+struct Channel {
+    service: Box<dyn NotificationService>,
+    settings: ChannelSettings,
+    limiter: Option<ChannelLimiter>,
+    enabled: AtomicBool,
+}
 
-        let discord = if let Some(discord_config) = &config.discord_config {
-            Some(DiscordService::new(discord_config.webhook_url.clone()))
-        } else {
-            None
-        };
+impl Channel {
+    fn info(&self, name: &str) -> ChannelInfo {
+        ChannelInfo {
+            name: name.to_string(),
+            kind: self.service.kind(),
+            enabled: self.enabled.load(Ordering::Relaxed),
+        }
+    }
+}
 
-        let slack = if let Some(slack_config) = &config.slack_config {
-            Some(SlackService::new(slack_config.webhook_url.clone()))
-        } else {
-            None
-        };
+/// Summary of a registered channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelInfo {
+    pub name: String,
+    pub kind: ChannelKind,
+    pub enabled: bool,
+}
 
-        let email = if let Some(email_config) = &config.email_config {
-            Some(EmailService::new(
-                email_config.smtp_server.clone(),
-                email_config.smtp_username.clone(),
-                email_config.smtp_password.clone(),
-                email_config.from_email.clone(),
-                email_config.to_email.clone(),
-            )?)
-        } else {
-            None
-        };
+/// Outcome of one channel's delivery attempt.
+#[derive(Debug, Serialize)]
+pub struct ChannelResult {
+    pub channel: String,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Set when the provider asked to slow down rather than failing.
+    #[serde(skip)]
+    pub retry_after: Option<Duration>,
+    #[serde(rename = "latency_ms", serialize_with = "serialize_millis")]
+    pub latency: Duration,
+}
+
+/// Per-channel results of a fan-out, in the order the channels were given.
+#[derive(Debug, Default, Serialize)]
+pub struct DeliveryReport {
+    pub results: Vec<ChannelResult>,
+}
 
-        Ok(Self {
-            webhook_secret: config.webhook_secret.clone().unwrap_or_default(),
-            whatsapp,
-            telegram,
-            discord,
-            slack,
-            email,
-        })
+impl DeliveryReport {
+    pub fn succeeded(&self) -> usize {
+        self.results.iter().filter(|r| r.success).count()
     }
 
-    pub async fn notify_all(&self, title: &str, message: &str) -> Result<()> {
-        let mut errors = Vec::new();
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.succeeded() - self.postponed()
+    }
 
-        // send notifications to all configured services
-        if let Some(whatsapp) = &self.whatsapp {
-            if let Err(e) = whatsapp.send(title, message).await {
-                errors.push(format!("WhatsApp: {}", e));
-            }
+    /// Deliveries the channel turned away for now because of rate limits.
+    pub fn postponed(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.retry_after.is_some())
+            .count()
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+/// Registry of notification channels keyed by their unique name.
+pub struct NotificationManager {
+    pub webhook_secrets: Vec<WebhookSecret>,
+    pub allow_sha1_signature: bool,
+    channels: BTreeMap<String, Channel>,
+}
+
+impl NotificationManager {
+    /// Builds the registry from the channels stored in `service_configs`.
+    pub fn new(config: &crate::config::Config, channels: &[ServiceConfig]) -> Result<Self> {
+        if config.webhook_secrets.is_empty() {
+            bail!("refusing to start without a webhook secret");
         }
 
-        if let Some(telegram) = &self.telegram {
-            if let Err(e) = telegram.send(title, message).await {
-                errors.push(format!("Telegram: {}", e));
-            }
+        let mut manager = Self {
+            webhook_secrets: config.webhook_secrets.clone(),
+            allow_sha1_signature: config.allow_sha1_signature,
+            channels: BTreeMap::new(),
+        };
+
+        let default_timeout = Duration::from_secs(config.notify_timeout_secs);
+
+        // init services based on available config
+        for ServiceConfig {
+            name,
+            enabled,
+            channel,
+        } in channels
+        {
+            let service: Box<dyn NotificationService> = match channel {
+                ChannelConfig::WhatsApp(c) => Box::new(
+                    WhatsAppService::new(c.api_key.expose().to_string(), c.phone_number.clone())
+                        .with_name(name),
+                ),
+                ChannelConfig::Telegram(c) => Box::new(
+                    TelegramService::new(c.bot_token.expose().to_string(), c.chat_id.clone())
+                        .with_name(name),
+                ),
+                ChannelConfig::Discord(c) => Box::new(
+                    DiscordService::new(c.webhook_url.expose().to_string()).with_name(name),
+                ),
+                ChannelConfig::Slack(c) => {
+                    Box::new(SlackService::new(c.webhook_url.expose().to_string()).with_name(name))
+                }
+                ChannelConfig::Email(c) => Box::new(
+                    EmailService::new(
+                        c.smtp_server.clone(),
+                        c.smtp_username.clone(),
+                        c.smtp_password.expose().to_string(),
+                        c.from_email.clone(),
+                        c.to_email.clone(),
+                    )?
+                    .with_name(name),
+                ),
+            };
+
+            let options = channel.options();
+            let channel_settings = ChannelSettings {
+                notify_unfollows: options.notify_unfollows,
+                timeout: options
+                    .timeout_secs
+                    .map_or(default_timeout, Duration::from_secs),
+                rate_limit_per_minute: options.rate_limit_per_minute,
+                rate_limit_burst: options.rate_limit_burst,
+            };
+            manager.register(service, channel_settings)?;
+            manager.set_enabled(name, *enabled)?;
         }
 
-        if let Some(discord) = &self.discord {
-            if let Err(e) = discord.send(title, message).await {
-                errors.push(format!("Discord: {}", e));
-            }
+        Ok(manager)
+    }
+
+    /// Adds a channel under its own name. Names must be unique, kinds need not be.
+    pub fn register(
+        &mut self,
+        service: Box<dyn NotificationService>,
+        settings: ChannelSettings,
+    ) -> Result<()> {
+        let name = service.name().to_string();
+        if self.channels.contains_key(&name) {
+            bail!("A channel named {} is already registered", name);
         }
 
-        if let Some(slack) = &self.slack {
-            if let Err(e) = slack.send(title, message).await {
-                errors.push(format!("Slack: {}", e));
-            }
+        let limiter = ratelimit::limiter(
+            service.kind(),
+            settings.rate_limit_per_minute,
+            settings.rate_limit_burst,
+        );
+        self.channels.insert(
+            name,
+            Channel {
+                service,
+                settings,
+                limiter,
+                enabled: AtomicBool::new(true),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn NotificationService>> {
+        self.channels.remove(name).map(|channel| channel.service)
+    }
+
+    pub fn list(&self) -> Vec<ChannelInfo> {
+        self.channels
+            .iter()
+            .map(|(name, channel)| channel.info(name))
+            .collect()
+    }
+
+    pub fn info(&self, name: &str) -> Result<ChannelInfo> {
+        Ok(self.channel(name)?.info(name))
+    }
+
+    /// Turns a channel on or off without removing it from the registry.
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        self.channel(name)?
+            .enabled
+            .store(enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Runs the channel's active probe, bounded by its timeout.
+    pub async fn health_check(&self, name: &str) -> Result<()> {
+        let channel = self.channel(name)?;
+        let timeout = channel.settings.timeout;
+        tokio::time::timeout(timeout, channel.service.health_check())
+            .await
+            .map_err(|_| anyhow!("Probe timed out after {:?}", timeout))?
+            .map_err(|e| anyhow!(scrub(&format!("{:#}", e))))
+    }
+
+    fn channel(&self, name: &str) -> Result<&Channel> {
+        self.channels
+            .get(name)
+            .ok_or_else(|| anyhow!("Channel {} is not configured", name))
+    }
+
+    /// Names of the enabled channels that want events with the given action.
+    /// Follows go everywhere, unfollows only to channels that opted in.
+    pub fn channels_for(&self, action: &str) -> Vec<String> {
+        self.channels
+            .iter()
+            .filter(|(_, channel)| channel.enabled.load(Ordering::Relaxed))
+            .filter(|(_, channel)| action != "unfollowed" || channel.settings.notify_unfollows)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Sends a single notification through one named channel, bounded by the
+    /// channel's timeout.
+    pub async fn send_to(&self, name: &str, notification: &Notification) -> Result<()> {
+        let channel = self.channel(name)?;
+        if !channel.enabled.load(Ordering::Relaxed) {
+            bail!("Channel {} is disabled", name);
         }
 
-        if let Some(email) = &self.email {
-            if let Err(e) = email.send(title, message).await {
-                errors.push(format!("Email: {}", e));
+        let timeout = channel.settings.timeout;
+        if let Some(limiter) = &channel.limiter {
+            // a short wait is absorbed here, a longer one goes back to the outbox
+            if tokio::time::timeout(timeout, limiter.until_ready())
+                .await
+                .is_err()
+            {
+                return Err(RateLimited {
+                    retry_after: timeout,
+                }
+                .into());
             }
         }
 
-        // if there were errors but at least one service succeeded, log warnings
-        // if all services failed, return an error
-        if !errors.is_empty() {
-            let error_msg = format!("Some notifications failed: {}", errors.join(", "));
-            tracing::warn!("{}", error_msg);
-
-            // only return error if ALL configured services failed
-            let total_services = [
-                self.whatsapp.is_some(),
-                self.telegram.is_some(),
-                self.discord.is_some(),
-                self.slack.is_some(),
-                self.email.is_some(),
-            ]
-            .iter()
-            .filter(|&&is_configured| is_configured)
-            .count();
+        tokio::time::timeout(timeout, channel.service.send(notification))
+            .await
+            .map_err(|_| anyhow!("Timed out after {:?}", timeout))?
+    }
 
-            if errors.len() == total_services && total_services > 0 {
-                return Err(anyhow::anyhow!(error_msg));
+    /// Sends to the given channels concurrently and reports on each of them.
+    /// `render` builds the notification as it should look on each channel.
+    pub async fn notify_channels<S, F>(&self, channels: &[S], render: F) -> DeliveryReport
+    where
+        S: AsRef<str>,
+        F: Fn(&ChannelInfo) -> Result<Notification> + Sync,
+    {
+        let render = &render;
+        let results = join_all(channels.iter().map(|channel| async move {
+            let channel = channel.as_ref();
+            let started = Instant::now();
+            let result = async {
+                let notification = render(&self.info(channel)?)?;
+                self.send_to(channel, &notification).await
             }
-        }
+            .await;
+            let latency = started.elapsed();
 
-        Ok(())
+            match result {
+                Ok(()) => ChannelResult {
+                    channel: channel.to_string(),
+                    success: true,
+                    error: None,
+                    retry_after: None,
+                    latency,
+                },
+                Err(e) => {
+                    // provider errors can quote a request URL with a token in it
+                    let error = scrub(&format!("{:#}", e));
+                    debug!(
+                        "{} notification failed after {:?}: {}",
+                        channel, latency, error
+                    );
+                    ChannelResult {
+                        channel: channel.to_string(),
+                        success: false,
+                        error: Some(error),
+                        retry_after: e
+                            .downcast_ref::<RateLimited>()
+                            .map(|limited| limited.retry_after),
+                        latency,
+                    }
+                }
+            }
+        }))
+        .await;
+
+        DeliveryReport { results }
+    }
+
+    pub async fn notify_all(&self, action: &str, notification: &Notification) -> DeliveryReport {
+        self.notify_channels(&self.channels_for(action), |_| Ok(notification.clone()))
+            .await
     }
 }
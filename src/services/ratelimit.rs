@@ -0,0 +1,118 @@
+use anyhow::Result;
+use governor::{
+    clock::DefaultClock,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter,
+};
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use serde_json::Value;
+use std::{fmt, num::NonZeroU32, time::Duration};
+use tracing::{debug, error};
+
+use super::ChannelKind;
+use crate::config::scrub;
+
+/// Wait used when a provider says 429 without saying for how long.
+const FALLBACK_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Longest wait a provider can impose before the delivery is tried again.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// A channel refused to send for now. The outbox retries after `retry_after`
+/// without counting it as a failed attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limited, retry after {:?}", self.retry_after)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+pub type ChannelLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+/// Token bucket for a channel. Overrides replace the provider default and a
+/// rate of 0 means unlimited.
+pub fn limiter(
+    kind: ChannelKind,
+    per_minute: Option<u32>,
+    burst: Option<u32>,
+) -> Option<ChannelLimiter> {
+    let default = kind.default_rate_limit();
+    let per_minute = NonZeroU32::new(per_minute.or(default.map(|(rate, _)| rate))?)?;
+    let burst = burst
+        .or(default.map(|(_, burst)| burst))
+        .and_then(NonZeroU32::new)
+        .unwrap_or(NonZeroU32::MIN);
+
+    Some(RateLimiter::direct(
+        Quota::per_minute(per_minute).allow_burst(burst),
+    ))
+}
+
+/// Turns a provider response into a result: 429s become [`RateLimited`],
+/// anything else unsuccessful is logged and reported as an API error.
+pub async fn check_response(response: Response, provider: &str) -> Result<()> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let header = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok());
+    let body = response.text().await?;
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        // Discord puts it at the top level, Telegram under `parameters`
+        let json = serde_json::from_str::<Value>(&body).ok();
+        let seconds = json
+            .as_ref()
+            .and_then(|json| {
+                json.get("retry_after")
+                    .or_else(|| json.pointer("/parameters/retry_after"))
+            })
+            .and_then(Value::as_f64)
+            .or(header);
+        let retry_after = retry_after(seconds);
+
+        debug!(
+            "{} rate limited the request, retrying after {:?}",
+            provider, retry_after
+        );
+        return Err(RateLimited { retry_after }.into());
+    }
+
+    error!("{} API error: {:?}", provider, scrub(&body));
+    Err(anyhow::anyhow!("{} API error", provider))
+}
+
+/// The provider's wait, capped so a bogus huge value can't park a delivery
+/// forever (or overflow `Duration`).
+fn retry_after(seconds: Option<f64>) -> Duration {
+    seconds
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map_or(FALLBACK_RETRY_AFTER, |seconds| {
+            Duration::from_secs_f64(seconds.min(MAX_RETRY_AFTER.as_secs_f64()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_is_clamped() {
+        assert_eq!(retry_after(Some(1.5)), Duration::from_millis(1500));
+        assert_eq!(retry_after(Some(1e30)), MAX_RETRY_AFTER);
+        assert_eq!(retry_after(Some(f64::INFINITY)), FALLBACK_RETRY_AFTER);
+        assert_eq!(retry_after(Some(-1.0)), FALLBACK_RETRY_AFTER);
+        assert_eq!(retry_after(None), FALLBACK_RETRY_AFTER);
+    }
+}
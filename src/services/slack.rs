@@ -1,9 +1,12 @@
-use crate::models::SlackMessage;
+use crate::models::{Notification, SlackAccessory, SlackBlock, SlackMessage, SlackText};
 use anyhow::{Result, Context};
 use reqwest::Client;
-use tracing::{info, error};
+use tracing::info;
+
+use super::{markup::Markup, ratelimit::check_response};
 
 pub struct SlackService {
+    name: String,
     client: Client,
     webhook_url: String,
 }
@@ -11,17 +14,65 @@ pub struct SlackService {
 impl SlackService {
     pub fn new(webhook_url: String) -> Self {
         Self {
+            name: "slack".to_string(),
             client: Client::new(),
             webhook_url,
         }
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl super::NotificationService for SlackService {
-    async fn send(&self, title: &str, message: &str) -> Result<()> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> super::ChannelKind {
+        super::ChannelKind::Slack
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let markup = Markup::SlackMrkdwn;
+        let title = markup.escape(&notification.title);
+        let mut text = format!("*{}*\n{}", title, markup.escape(&notification.body));
+        if let Some(link) = &notification.link {
+            text.push_str(&format!("\n<{}|View on GitHub>", markup.escape(link)));
+        }
+
+        let mut blocks = vec![SlackBlock::Section {
+            text: SlackText::mrkdwn(text),
+            accessory: notification.image.as_ref().map(|image| SlackAccessory::Image {
+                image_url: image.clone(),
+                alt_text: notification
+                    .actor
+                    .as_ref()
+                    .map_or_else(|| notification.title.clone(), |actor| actor.login.clone()),
+            }),
+        }];
+        if !notification.fields.is_empty() {
+            blocks.push(SlackBlock::Context {
+                elements: notification
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        SlackText::mrkdwn(format!(
+                            "*{}:* {}",
+                            markup.escape(&field.name),
+                            markup.escape(&field.value)
+                        ))
+                    })
+                    .collect(),
+            });
+        }
+
         let payload = SlackMessage {
-            text: format!("*{}*\n{}", title, message),
+            text: format!("*{}*\n{}", title, markup.escape(&notification.plain_text())),
+            blocks,
         };
 
         let response = self.client
@@ -31,10 +82,7 @@ impl super::NotificationService for SlackService {
             .await
             .context("Failed to send Slack message")?;
 
-        if !response.status().is_success() {
-            error!("Slack API error: {:?}", response.text().await?);
-            return Err(anyhow::anyhow!("Slack API error"));
-        }
+        check_response(response, "Slack").await?;
 
         info!("Slack notification sent successfully");
         Ok(())
@@ -1,9 +1,19 @@
-use crate::models::TelegramMessage;
+use crate::models::{Notification, TelegramMessage, TelegramPhoto};
 use anyhow::{Result, Context};
 use reqwest::Client;
-use tracing::{info, error};
+use tracing::info;
+
+use super::{markup::Markup, ratelimit::check_response};
+
+/// Telegram rejects photo captions longer than this many characters.
+const CAPTION_LIMIT: usize = 1024;
+/// Same for message texts.
+const MESSAGE_LIMIT: usize = 4096;
+/// Longest title kept, so an oversized one still leaves room for the body.
+const TITLE_LIMIT: usize = 256;
 
 pub struct TelegramService {
+    name: String,
     client: Client,
     bot_token: String,
     chat_id: String,
@@ -12,37 +22,127 @@ pub struct TelegramService {
 impl TelegramService {
     pub fn new(bot_token: String, chat_id: String) -> Self {
         Self {
+            name: "telegram".to_string(),
             client: Client::new(),
             bot_token,
             chat_id,
         }
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl super::NotificationService for TelegramService {
-    async fn send(&self, title: &str, message: &str) -> Result<()> {
-        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token);
-        
-        let payload = TelegramMessage {
-            chat_id: self.chat_id.clone(),
-            text: format!("*{}*\n{}", title, message),
-            parse_mode: "Markdown".to_string(),
-        };
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> super::ChannelKind {
+        super::ChannelKind::Telegram
+    }
 
-        let response = self.client
-            .post(&url)
-            .json(&payload)
+    /// `getMe` only succeeds with a valid bot token.
+    async fn health_check(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(format!("https://api.telegram.org/bot{}/getMe", self.bot_token))
             .send()
             .await
-            .context("Failed to send Telegram message")?;
+            .context("Failed to reach Telegram")?;
 
         if !response.status().is_success() {
-            error!("Telegram API error: {:?}", response.text().await?);
-            return Err(anyhow::anyhow!("Telegram API error"));
+            return Err(anyhow::anyhow!("Telegram getMe returned {}", response.status()));
         }
+        Ok(())
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let title = &notification.title;
+        let body = notification.plain_text();
+
+        // show the image with the text as its caption when there is one
+        let request = match &notification.image {
+            Some(photo) => self
+                .client
+                .post(format!("https://api.telegram.org/bot{}/sendPhoto", self.bot_token))
+                .json(&TelegramPhoto {
+                    chat_id: self.chat_id.clone(),
+                    photo: photo.clone(),
+                    caption: message_text(title, &body, CAPTION_LIMIT),
+                    parse_mode: "HTML".to_string(),
+                }),
+            None => self
+                .client
+                .post(format!("https://api.telegram.org/bot{}/sendMessage", self.bot_token))
+                .json(&TelegramMessage {
+                    chat_id: self.chat_id.clone(),
+                    text: message_text(title, &body, MESSAGE_LIMIT),
+                    parse_mode: "HTML".to_string(),
+                }),
+        };
+
+        let response = request
+            .send()
+            .await
+            .context("Failed to send Telegram message")?;
+
+        check_response(response, "Telegram").await?;
 
         info!("Telegram notification sent successfully");
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Bold title over the body, both trimmed so the whole fits in `limit`.
+fn message_text(title: &str, body: &str, limit: usize) -> String {
+    // the limit counts visible characters, so trim before adding markup
+    let title = truncate(title, TITLE_LIMIT);
+    let body = truncate(body, limit.saturating_sub(title.chars().count() + 1).max(1));
+    let markup = Markup::TelegramHtml;
+    format!("<b>{}</b>\n{}", markup.escape(&title), markup.escape(&body))
+}
+
+fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(limit - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visible_len(text: &str) -> usize {
+        text.trim_start_matches("<b>")
+            .replacen("</b>", "", 1)
+            .chars()
+            .count()
+    }
+
+    #[test]
+    fn short_text_is_kept_whole() {
+        assert_eq!(
+            message_text("Hi & bye", "body", CAPTION_LIMIT),
+            "<b>Hi &amp; bye</b>\nbody"
+        );
+    }
+
+    #[test]
+    fn long_title_and_body_fit_the_limit() {
+        let title = "t".repeat(5000);
+        let body = "b".repeat(5000);
+        for limit in [CAPTION_LIMIT, MESSAGE_LIMIT] {
+            let text = message_text(&title, &body, limit);
+            assert_eq!(visible_len(&text), limit);
+            assert!(text.contains("t…</b>"));
+            assert!(text.ends_with("b…"));
+        }
+    }
+}
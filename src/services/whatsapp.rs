@@ -1,9 +1,12 @@
-use crate::models::WhatsAppMessage;
+use crate::models::{Notification, WhatsAppMessage};
 use anyhow::{Result, Context};
 use reqwest::Client;
-use tracing::{info, error};
+use tracing::info;
+
+use super::ratelimit::check_response;
 
 pub struct WhatsAppService {
+    name: String,
     client: Client,
     api_key: String,
     phone_number: String,
@@ -12,16 +15,30 @@ pub struct WhatsAppService {
 impl WhatsAppService {
     pub fn new(api_key: String, phone_number: String) -> Self {
         Self {
+            name: "whatsapp".to_string(),
             client: Client::new(),
             api_key,
             phone_number,
         }
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
 }
 
 #[async_trait::async_trait]
 impl super::NotificationService for WhatsAppService {
-    async fn send(&self, title: &str, message: &str) -> Result<()> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> super::ChannelKind {
+        super::ChannelKind::WhatsApp
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<()> {
         let url = "https://graph.facebook.com/v13.0/YOUR_PHONE_NUMBER_ID/messages";
         
         let payload = WhatsAppMessage {
@@ -29,7 +46,7 @@ impl super::NotificationService for WhatsAppService {
             to: self.phone_number.clone(),
             msg_type: "text".to_string(),
             text: crate::models::WhatsAppText {
-                body: format!("{}\n{}", title, message),
+                body: format!("{}\n{}", notification.title, notification.plain_text()),
             },
         };
 
@@ -41,10 +58,7 @@ impl super::NotificationService for WhatsAppService {
             .await
             .context("Failed to send WhatsApp message")?;
 
-        if !response.status().is_success() {
-            error!("WhatsApp API error: {:?}", response.text().await?);
-            return Err(anyhow::anyhow!("WhatsApp API error"));
-        }
+        check_response(response, "WhatsApp").await?;
 
         info!("WhatsApp notification sent successfully");
         Ok(())
@@ -0,0 +1,52 @@
+use arc_swap::ArcSwap;
+use std::sync::Arc;
+
+use crate::{
+    config::OutboxConfig,
+    metrics::Metrics,
+    outbox::DeliveryQueue,
+    services::{health::ChannelHealth, NotificationManager},
+    storage::Database,
+    templates::TemplateRenderer,
+};
+
+/// Shared state handed to every request handler and background task. The
+/// channel registry and templates can be swapped by a config reload, so load
+/// them once per request rather than holding on to the guard.
+#[derive(Clone)]
+pub struct AppState {
+    pub manager: Arc<ArcSwap<NotificationManager>>,
+    pub db: Database,
+    pub outbox: OutboxConfig,
+    /// Seconds an `X-GitHub-Delivery` GUID counts as already seen.
+    pub delivery_retention_secs: u64,
+    pub queue: DeliveryQueue,
+    pub templates: Arc<ArcSwap<TemplateRenderer>>,
+    pub health: Arc<ChannelHealth>,
+    pub metrics: Arc<Metrics>,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State around `db` with no channels and the built-in templates, for
+    /// tests. Events scheduled for delivery arrive on the returned receiver.
+    pub(crate) fn for_tests(db: Database) -> (Self, tokio::sync::mpsc::Receiver<i64>) {
+        let config = crate::config::Config::for_tests();
+        let (queue, queued_events) = DeliveryQueue::new();
+        let state = Self {
+            manager: Arc::new(ArcSwap::from_pointee(
+                NotificationManager::new(&config, &[]).unwrap(),
+            )),
+            db,
+            outbox: config.outbox_config,
+            delivery_retention_secs: config.delivery_retention_secs,
+            queue,
+            templates: Arc::new(ArcSwap::from_pointee(
+                TemplateRenderer::new(None, &config.templates, &[]).unwrap(),
+            )),
+            health: Arc::default(),
+            metrics: Arc::new(Metrics::new().unwrap()),
+        };
+        (state, queued_events)
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+use super::{cipher::ConfigCipher, Database};
+use crate::config::ChannelConfig;
+
+/// Fields that hold credentials and are encrypted before they are stored.
+const SECRET_FIELDS: &[&str] = &["api_key", "bot_token", "webhook_url", "smtp_password"];
+
+/// A row of `service_configs` as stored, credentials still encrypted.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ServiceConfigRow {
+    pub name: String,
+    pub service_type: String,
+    pub enabled: bool,
+    pub config_json: String,
+    pub source: String,
+}
+
+/// A channel from `service_configs` with its credentials decrypted.
+#[derive(Clone, PartialEq)]
+pub struct ServiceConfig {
+    pub name: String,
+    pub enabled: bool,
+    pub channel: ChannelConfig,
+}
+
+impl Database {
+    pub async fn service_config_rows(&self) -> Result<Vec<ServiceConfigRow>> {
+        sqlx::query_as(
+            "SELECT name, service_type, enabled, config_json, source \
+             FROM service_configs ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to load service configs")
+    }
+
+    /// Makes `service_configs` match the channels declared in the config file
+    /// and env vars. Their `enabled` flag stays under runtime control, and
+    /// channels added at runtime are left alone. Without a cipher their
+    /// credentials are not stored at all, [`ServiceConfigRow::decode`] takes
+    /// them from the declared channel instead.
+    pub async fn sync_service_configs(
+        &self,
+        channels: &BTreeMap<String, ChannelConfig>,
+        cipher: Option<&ConfigCipher>,
+    ) -> Result<()> {
+        let existing: BTreeMap<String, ServiceConfigRow> = self
+            .service_config_rows()
+            .await?
+            .into_iter()
+            .map(|row| (row.name.clone(), row))
+            .collect();
+
+        for (name, channel) in channels {
+            let (service_type, mut fields) = split_kind(channel)?;
+            if cipher.is_none() {
+                strip_credentials(&mut fields);
+            }
+
+            // ciphertexts differ on every write, so compare what they decrypt to
+            let unchanged = existing.get(name).is_some_and(|row| {
+                row.service_type == service_type
+                    && row.source == "config"
+                    && serde_json::from_str(&row.config_json)
+                        .ok()
+                        .and_then(|stored| decrypt_fields(stored, cipher).ok())
+                        .as_ref()
+                        == Some(&fields)
+            });
+            if unchanged {
+                continue;
+            }
+
+            let config_json = serde_json::to_string(&encrypt_fields(fields, cipher)?)?;
+            sqlx::query(
+                "INSERT INTO service_configs (name, service_type, config_json, source) \
+                 VALUES (?1, ?2, ?3, 'config') \
+                 ON CONFLICT(name) DO UPDATE SET \
+                     service_type = ?2, config_json = ?3, source = 'config', \
+                     updated_at = CURRENT_TIMESTAMP",
+            )
+            .bind(name)
+            .bind(&service_type)
+            .bind(config_json)
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to store config for channel {}", name))?;
+            if cipher.is_some() {
+                info!("Stored config for channel {}", name);
+            } else {
+                info!(
+                    "Stored config for channel {}, its credentials are kept in memory as SERVICE_CONFIG_KEY is not set",
+                    name
+                );
+            }
+        }
+
+        for row in existing.values() {
+            if row.source == "config" && !channels.contains_key(&row.name) {
+                sqlx::query("DELETE FROM service_configs WHERE name = ?")
+                    .bind(&row.name)
+                    .execute(&self.pool)
+                    .await
+                    .with_context(|| format!("Failed to remove channel {}", row.name))?;
+                info!("Removed channel {}, it is no longer configured", row.name);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ServiceConfigRow {
+    /// Decrypts the row's credentials and checks its settings. `declared` is
+    /// the channel of that name from the config, which fills in credentials a
+    /// config row was stored without.
+    pub fn decode(
+        &self,
+        cipher: Option<&ConfigCipher>,
+        declared: Option<&ChannelConfig>,
+    ) -> Result<ServiceConfig> {
+        let stored: Map<String, Value> = serde_json::from_str(&self.config_json)
+            .with_context(|| format!("Channel {} has malformed config_json", self.name))?;
+
+        for field in SECRET_FIELDS {
+            let plain = stored
+                .get(*field)
+                .and_then(Value::as_str)
+                .is_some_and(|value| !ConfigCipher::is_encrypted(value));
+            if plain {
+                warn!(
+                    "Channel {} stores {} unencrypted in service_configs",
+                    self.name, field
+                );
+            }
+        }
+
+        let mut fields = decrypt_fields(stored, cipher)
+            .with_context(|| format!("Channel {} in service_configs is unreadable", self.name))?;
+        if let Some(declared) = declared.filter(|_| self.source == "config") {
+            let (_, declared) = split_kind(declared)?;
+            for (field, value) in declared {
+                if SECRET_FIELDS.contains(&field.as_str()) {
+                    fields.entry(field).or_insert(value);
+                }
+            }
+        }
+        fields.insert("kind".to_string(), Value::String(self.service_type.clone()));
+        let channel = serde_json::from_value(Value::Object(fields))
+            .with_context(|| format!("Channel {} in service_configs is invalid", self.name))?;
+
+        Ok(ServiceConfig {
+            name: self.name.clone(),
+            enabled: self.enabled,
+            channel,
+        })
+    }
+}
+
+/// Splits a channel into its kind and the fields stored in `config_json`.
+fn split_kind(channel: &ChannelConfig) -> Result<(String, Map<String, Value>)> {
+    let Value::Object(mut fields) = serde_json::to_value(channel)? else {
+        bail!("Channel config did not serialize to an object");
+    };
+    let kind = fields
+        .remove("kind")
+        .and_then(|kind| kind.as_str().map(str::to_string))
+        .ok_or_else(|| anyhow!("Channel config has no kind"))?;
+    Ok((kind, fields))
+}
+
+fn strip_credentials(fields: &mut Map<String, Value>) {
+    fields.retain(|field, _| !SECRET_FIELDS.contains(&field.as_str()));
+}
+
+fn encrypt_fields(
+    mut fields: Map<String, Value>,
+    cipher: Option<&ConfigCipher>,
+) -> Result<Map<String, Value>> {
+    for field in SECRET_FIELDS {
+        let Some(Value::String(value)) = fields.get_mut(*field) else {
+            continue;
+        };
+        let cipher =
+            cipher.ok_or_else(|| anyhow!("SERVICE_CONFIG_KEY is needed to store {}", field))?;
+        *value = cipher.encrypt(value)?;
+    }
+    Ok(fields)
+}
+
+fn decrypt_fields(
+    mut fields: Map<String, Value>,
+    cipher: Option<&ConfigCipher>,
+) -> Result<Map<String, Value>> {
+    for (field, value) in fields.iter_mut() {
+        let Value::String(text) = value else {
+            continue;
+        };
+        if ConfigCipher::is_encrypted(text) {
+            let cipher = cipher
+                .ok_or_else(|| anyhow!("SERVICE_CONFIG_KEY is needed to decrypt {}", field))?;
+            *text = cipher.decrypt(text)?;
+        }
+    }
+    Ok(fields)
+}
@@ -0,0 +1,109 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, bail, Context, Result};
+
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// Encrypts channel credentials stored in `service_configs` with AES-256-GCM.
+/// Values are stored as `enc:v1:` followed by the hex of nonce and ciphertext.
+#[derive(Clone)]
+pub struct ConfigCipher {
+    cipher: Aes256Gcm,
+}
+
+impl ConfigCipher {
+    /// `key` is 32 bytes, hex encoded.
+    pub fn new(key: &str) -> Result<Self> {
+        let key = hex::decode(key.trim()).context("SERVICE_CONFIG_KEY must be hex encoded")?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow!("SERVICE_CONFIG_KEY must be 32 bytes (64 hex characters)"))?;
+        Ok(Self { cipher })
+    }
+
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(PREFIX)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt credential"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{}{}", PREFIX, hex::encode(sealed)))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> Result<String> {
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            bail!("Credential is not encrypted");
+        };
+        let sealed = hex::decode(encoded).context("Encrypted credential is not valid hex")?;
+        if sealed.len() < NONCE_LEN {
+            bail!("Encrypted credential is truncated");
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt credential, is SERVICE_CONFIG_KEY right?"))?;
+        String::from_utf8(plaintext).context("Decrypted credential is not UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn round_trips_with_a_fresh_nonce() {
+        let cipher = ConfigCipher::new(KEY).unwrap();
+        let first = cipher.encrypt("xoxb-token").unwrap();
+        let second = cipher.encrypt("xoxb-token").unwrap();
+
+        assert!(ConfigCipher::is_encrypted(&first));
+        assert!(!first.contains("xoxb-token"));
+        assert_ne!(first, second);
+        assert_eq!(cipher.decrypt(&first).unwrap(), "xoxb-token");
+        assert_eq!(cipher.decrypt(&second).unwrap(), "xoxb-token");
+    }
+
+    #[test]
+    fn rejects_tampered_or_truncated_values() {
+        let cipher = ConfigCipher::new(KEY).unwrap();
+        let sealed = cipher.encrypt("xoxb-token").unwrap();
+
+        // flip a bit in the last byte of the ciphertext
+        let mut bytes = hex::decode(&sealed[PREFIX.len()..]).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = format!("{}{}", PREFIX, hex::encode(bytes));
+        assert!(cipher.decrypt(&tampered).is_err());
+
+        assert!(cipher.decrypt(&sealed[..PREFIX.len() + 8]).is_err());
+        assert!(cipher.decrypt("xoxb-token").is_err());
+    }
+
+    #[test]
+    fn rejects_another_key() {
+        let sealed = ConfigCipher::new(KEY)
+            .unwrap()
+            .encrypt("xoxb-token")
+            .unwrap();
+        let other = ConfigCipher::new(&"ab".repeat(32)).unwrap();
+        assert!(other.decrypt(&sealed).is_err());
+    }
+
+    #[test]
+    fn key_must_be_32_hex_bytes() {
+        assert!(ConfigCipher::new("not hex").is_err());
+        assert!(ConfigCipher::new("abcd").is_err());
+    }
+}
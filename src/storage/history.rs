@@ -0,0 +1,288 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sqlx::{query::QueryAs, sqlite::SqliteArguments, Sqlite};
+
+use super::{Database, DeliveryStatus};
+
+/// Filters shared by the event and delivery listings. Timestamps are
+/// anything SQLite's `datetime()` understands, compared in UTC.
+#[derive(Debug, Default)]
+pub struct HistoryFilter {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub login: Option<String>,
+    pub status: Option<DeliveryStatus>,
+    pub channel: Option<String>,
+}
+
+/// Outcome of [`Database::cancel`].
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Cancellation {
+    pub cancelled: u64,
+    /// Deliveries being sent at the time, which were left to finish.
+    pub in_flight: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub number: u32,
+    pub size: u32,
+}
+
+impl Page {
+    fn offset(&self) -> i64 {
+        i64::from(self.number.saturating_sub(1)) * i64::from(self.size)
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EventRecord {
+    pub id: i64,
+    pub event_type: String,
+    pub sender_login: String,
+    pub created_at: Option<String>,
+    pub processed: bool,
+    /// `accepted`, or `skipped` for webhooks that were not announced.
+    pub status: String,
+    pub skip_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DeliveryRecord {
+    pub id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub sender_login: String,
+    pub channel: String,
+    pub status: String,
+    pub attempts: i64,
+    pub error_message: Option<String>,
+    pub created_at: Option<String>,
+    pub last_attempt_at: Option<String>,
+    pub next_attempt_at: Option<String>,
+    pub sent_at: Option<String>,
+}
+
+const DELIVERY_COLUMNS: &str = "sn.id, sn.event_id, ne.event_type, ne.sender_login, \
+     sn.service_type AS channel, sn.status, sn.attempts, sn.error_message, ne.created_at, \
+     sn.last_attempt_at, sn.next_attempt_at, sn.sent_at";
+
+// ?1 since, ?2 until, ?3 login, ?4 status, ?5 channel
+const EVENT_FILTER: &str = "(?1 IS NULL OR ne.created_at >= datetime(?1)) \
+     AND (?2 IS NULL OR ne.created_at <= datetime(?2)) \
+     AND (?3 IS NULL OR ne.sender_login = ?3)";
+const DELIVERY_FILTER: &str = "(?4 IS NULL OR sn.status = ?4) \
+     AND (?5 IS NULL OR sn.service_type = ?5)";
+
+impl Database {
+    /// Checks that a filter timestamp is something SQLite can compare against.
+    pub async fn is_valid_timestamp(&self, value: &str) -> Result<bool> {
+        let parsed: Option<String> = sqlx::query_scalar("SELECT datetime(?)")
+            .bind(value)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(parsed.is_some())
+    }
+
+    /// Events matching the filter, newest first, with the total match count.
+    /// Status and channel match events with at least one such delivery.
+    pub async fn events(
+        &self,
+        filter: &HistoryFilter,
+        page: Page,
+    ) -> Result<(Vec<EventRecord>, i64)> {
+        let condition = format!(
+            "{} AND ((?4 IS NULL AND ?5 IS NULL) OR EXISTS ( \
+                 SELECT 1 FROM service_notifications sn \
+                 WHERE sn.event_id = ne.id AND {}))",
+            EVENT_FILTER, DELIVERY_FILTER
+        );
+
+        let count_sql = format!(
+            "SELECT COUNT(*) FROM notification_events ne WHERE {}",
+            condition
+        );
+        let (total,): (i64,) = bind_filter(sqlx::query_as(&count_sql), filter)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to count events")?;
+
+        let sql = format!(
+            "SELECT ne.id, ne.event_type, ne.sender_login, ne.created_at, \
+                 COALESCE(ne.processed, FALSE) AS processed, ne.status, ne.skip_reason \
+             FROM notification_events ne WHERE {} \
+             ORDER BY ne.id DESC LIMIT ?6 OFFSET ?7",
+            condition
+        );
+        let events = bind_filter(sqlx::query_as(&sql), filter)
+            .bind(i64::from(page.size))
+            .bind(page.offset())
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to load events")?;
+
+        Ok((events, total))
+    }
+
+    pub async fn event(&self, event_id: i64) -> Result<Option<EventRecord>> {
+        sqlx::query_as(
+            "SELECT id, event_type, sender_login, created_at, \
+                 COALESCE(processed, FALSE) AS processed, status, skip_reason \
+             FROM notification_events WHERE id = ?",
+        )
+        .bind(event_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to load event")
+    }
+
+    /// Deliveries matching the filter, newest first, with the total match count.
+    pub async fn deliveries(
+        &self,
+        filter: &HistoryFilter,
+        page: Page,
+    ) -> Result<(Vec<DeliveryRecord>, i64)> {
+        let from = format!(
+            "FROM service_notifications sn \
+             JOIN notification_events ne ON ne.id = sn.event_id \
+             WHERE {} AND {}",
+            EVENT_FILTER, DELIVERY_FILTER
+        );
+
+        let count_sql = format!("SELECT COUNT(*) {}", from);
+        let (total,): (i64,) = bind_filter(sqlx::query_as(&count_sql), filter)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to count deliveries")?;
+
+        let sql = format!(
+            "SELECT {} {} ORDER BY sn.id DESC LIMIT ?6 OFFSET ?7",
+            DELIVERY_COLUMNS, from
+        );
+        let deliveries = bind_filter(sqlx::query_as(&sql), filter)
+            .bind(i64::from(page.size))
+            .bind(page.offset())
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to load deliveries")?;
+
+        Ok((deliveries, total))
+    }
+
+    /// All deliveries of the given events, grouped by the caller.
+    pub async fn deliveries_of(&self, event_ids: &[i64]) -> Result<Vec<DeliveryRecord>> {
+        let sql = format!(
+            "SELECT {} FROM service_notifications sn \
+             JOIN notification_events ne ON ne.id = sn.event_id \
+             WHERE sn.event_id IN (SELECT value FROM json_each(?)) \
+             ORDER BY sn.id",
+            DELIVERY_COLUMNS
+        );
+        sqlx::query_as(&sql)
+            .bind(serde_json::to_string(event_ids)?)
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to load deliveries")
+    }
+
+    pub async fn delivery(&self, notification_id: i64) -> Result<Option<DeliveryRecord>> {
+        let sql = format!(
+            "SELECT {} FROM service_notifications sn \
+             JOIN notification_events ne ON ne.id = sn.event_id \
+             WHERE sn.id = ?",
+            DELIVERY_COLUMNS
+        );
+        sqlx::query_as(&sql)
+            .bind(notification_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to load delivery")
+    }
+
+    /// Puts finished deliveries (sent, dead or cancelled) back in the queue
+    /// with a fresh attempt budget. Ones still queued or in flight are left
+    /// alone. With no `notification_id` every delivery of the event is
+    /// resent. Returns how many rows were reset.
+    pub async fn resend(&self, event_id: i64, notification_id: Option<i64>) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let reset = sqlx::query(
+            "UPDATE service_notifications \
+             SET status = ?, attempts = 0, next_attempt_at = NULL, error_message = NULL, sent_at = NULL \
+             WHERE event_id = ? AND (?3 IS NULL OR id = ?3) \
+             AND status IN ('sent', 'dead', 'cancelled')",
+        )
+        .bind(DeliveryStatus::Pending.as_str())
+        .bind(event_id)
+        .bind(notification_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to reset deliveries")?
+        .rows_affected();
+
+        if reset > 0 {
+            sqlx::query("UPDATE notification_events SET processed = FALSE WHERE id = ?")
+                .bind(event_id)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to reopen event")?;
+        }
+
+        tx.commit().await?;
+        Ok(reset)
+    }
+
+    /// Cancels deliveries that have not gone out yet, either one or all of an
+    /// event's. Deliveries a worker is sending right now can't be stopped and
+    /// are counted as in flight instead.
+    pub async fn cancel(
+        &self,
+        event_id: i64,
+        notification_id: Option<i64>,
+    ) -> Result<Cancellation> {
+        let mut tx = self.pool.begin().await?;
+
+        let cancelled = sqlx::query(
+            "UPDATE service_notifications \
+             SET status = ?, next_attempt_at = NULL \
+             WHERE event_id = ? AND (?3 IS NULL OR id = ?3) \
+             AND status IN ('pending', 'failed')",
+        )
+        .bind(DeliveryStatus::Cancelled.as_str())
+        .bind(event_id)
+        .bind(notification_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to cancel deliveries")?
+        .rows_affected();
+
+        let (in_flight,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM service_notifications \
+             WHERE event_id = ? AND (?2 IS NULL OR id = ?2) AND status = ?",
+        )
+        .bind(event_id)
+        .bind(notification_id)
+        .bind(DeliveryStatus::InFlight.as_str())
+        .fetch_one(&mut *tx)
+        .await
+        .context("Failed to count deliveries in flight")?;
+
+        tx.commit().await?;
+        self.complete_event(event_id).await?;
+        Ok(Cancellation {
+            cancelled,
+            in_flight: in_flight as u64,
+        })
+    }
+}
+
+type FilterQuery<'q, O> = QueryAs<'q, Sqlite, O, SqliteArguments<'q>>;
+
+fn bind_filter<'q, O>(query: FilterQuery<'q, O>, filter: &'q HistoryFilter) -> FilterQuery<'q, O> {
+    query
+        .bind(filter.since.as_deref())
+        .bind(filter.until.as_deref())
+        .bind(filter.login.as_deref())
+        .bind(filter.status.map(|status| status.as_str()))
+        .bind(filter.channel.as_deref())
+}
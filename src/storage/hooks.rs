@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sqlx::types::Json;
+
+use super::Database;
+
+/// A webhook as last described by its ping.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct HookRecord {
+    pub hook_id: i64,
+    pub hook_type: String,
+    pub target: Option<String>,
+    pub events: Json<Vec<String>>,
+    pub content_type: Option<String>,
+    /// Setup issues found when it pinged, empty if it looked right.
+    pub problems: Json<Vec<String>>,
+    pub pinged_at: Option<String>,
+}
+
+impl Database {
+    /// Stores or refreshes a hook after a ping.
+    pub async fn record_hook(&self, hook: &HookRecord) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO webhook_hooks \
+             (hook_id, hook_type, target, events, content_type, problems, pinged_at) \
+             VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP) \
+             ON CONFLICT (hook_id) DO UPDATE SET \
+             hook_type = excluded.hook_type, target = excluded.target, \
+             events = excluded.events, content_type = excluded.content_type, \
+             problems = excluded.problems, pinged_at = excluded.pinged_at",
+        )
+        .bind(hook.hook_id)
+        .bind(&hook.hook_type)
+        .bind(&hook.target)
+        .bind(&hook.events)
+        .bind(&hook.content_type)
+        .bind(&hook.problems)
+        .execute(&self.pool)
+        .await
+        .context("Failed to record webhook")?;
+
+        Ok(())
+    }
+
+    pub async fn hooks(&self) -> Result<Vec<HookRecord>> {
+        sqlx::query_as(
+            "SELECT hook_id, hook_type, target, events, content_type, problems, pinged_at \
+             FROM webhook_hooks ORDER BY hook_id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to load webhooks")
+    }
+}
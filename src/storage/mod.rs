@@ -0,0 +1,554 @@
+use anyhow::{Context, Result};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Sqlite, SqlitePool, Transaction,
+};
+use std::str::FromStr;
+use tracing::info;
+
+use crate::models::{GitHubEvent, Sender};
+
+mod channels;
+mod cipher;
+mod history;
+mod hooks;
+
+pub use channels::{ServiceConfig, ServiceConfigRow};
+pub use cipher::ConfigCipher;
+pub use history::{Cancellation, DeliveryRecord, EventRecord, HistoryFilter, Page};
+pub use hooks::HookRecord;
+
+/// Migrations embedded from `schemas/migrations` at compile time.
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./schemas/migrations");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    Pending,
+    /// Leased by an outbox worker that is sending it right now.
+    InFlight,
+    Sent,
+    /// The last attempt failed and a retry is scheduled.
+    Failed,
+    /// Every attempt failed; the delivery will not be retried.
+    Dead,
+    /// Withdrawn through the admin API before it went out.
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::InFlight => "in_flight",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Dead => "dead",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "in_flight" => Ok(DeliveryStatus::InFlight),
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            "dead" => Ok(DeliveryStatus::Dead),
+            "cancelled" => Ok(DeliveryStatus::Cancelled),
+            _ => Err(anyhow::anyhow!("Unknown delivery status {}", value)),
+        }
+    }
+}
+
+/// A row of `v_pending_notifications` that is due for delivery.
+#[derive(Debug, sqlx::FromRow)]
+pub struct PendingNotification {
+    pub notification_id: i64,
+    pub event_id: i64,
+    pub service_type: String,
+    pub attempts: i64,
+    pub payload: Option<String>,
+    /// When the event was received, in unix seconds.
+    pub received_at: Option<i64>,
+}
+
+/// What became of an incoming event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enqueued {
+    Created(i64),
+    /// The webhook delivery was accepted before; holds the event it created.
+    Duplicate(Option<i64>),
+}
+
+/// An incoming webhook that was acknowledged without being announced.
+pub struct SkippedEvent<'a> {
+    /// The event key, or the `X-GitHub-Event` name when it isn't supported.
+    pub event_type: &'a str,
+    pub sender: Option<&'a Sender>,
+    /// The raw payload, kept for inspection.
+    pub payload: &'a str,
+    pub reason: &'a str,
+}
+
+#[derive(Clone)]
+pub struct Database {
+    pool: SqlitePool,
+}
+
+impl Database {
+    /// Opens (or creates) the database and brings its schema up to date.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)
+            .with_context(|| format!("Invalid database url {}", url))?
+            .create_if_missing(true)
+            .foreign_keys(true);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await
+            .with_context(|| format!("Failed to open database {}", url))?;
+
+        MIGRATOR
+            .run(&pool)
+            .await
+            .context("Failed to apply database migrations")?;
+        info!("Database ready at {}", url);
+
+        Ok(Self { pool })
+    }
+
+    /// Number of outstanding deliveries per status.
+    pub async fn outbox_depth(&self) -> Result<Vec<(String, i64)>> {
+        sqlx::query_as(
+            "SELECT status, COUNT(*) FROM service_notifications \
+             WHERE status IN ('pending', 'failed', 'in_flight') GROUP BY status",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to count outstanding deliveries")
+    }
+
+    /// Checks that the database answers queries.
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .context("Database is unreachable")?;
+        Ok(())
+    }
+
+    /// Stores an event together with one `pending` delivery row per channel.
+    pub async fn enqueue_event(&self, event: &GitHubEvent, channels: &[String]) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+        let event_id = Self::insert_event(&mut tx, event, channels).await?;
+        tx.commit().await?;
+        Ok(event_id)
+    }
+
+    /// Like `enqueue_event`, but only once per `X-GitHub-Delivery` GUID within
+    /// the retention window.
+    pub async fn enqueue_webhook(
+        &self,
+        delivery_id: &str,
+        retention_secs: u64,
+        event: &GitHubEvent,
+        channels: &[String],
+    ) -> Result<Enqueued> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(duplicate) = Self::record_delivery(&mut tx, delivery_id, retention_secs).await?
+        {
+            // still commit the prune
+            tx.commit().await?;
+            return Ok(duplicate);
+        }
+
+        let event_id = Self::insert_event(&mut tx, event, channels).await?;
+        Self::link_delivery(&mut tx, delivery_id, event_id).await?;
+
+        tx.commit().await?;
+        Ok(Enqueued::Created(event_id))
+    }
+
+    /// Stores a webhook that won't be announced as a `skipped` event without
+    /// deliveries. Redeliveries are recognised as in `enqueue_webhook`.
+    pub async fn record_skipped(
+        &self,
+        skipped: &SkippedEvent<'_>,
+        delivery_id: Option<&str>,
+        retention_secs: u64,
+    ) -> Result<Enqueued> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(delivery_id) = delivery_id {
+            if let Some(duplicate) =
+                Self::record_delivery(&mut tx, delivery_id, retention_secs).await?
+            {
+                tx.commit().await?;
+                return Ok(duplicate);
+            }
+        }
+
+        let event_id = sqlx::query(
+            "INSERT INTO notification_events \
+             (event_type, sender_login, sender_avatar_url, sender_html_url, payload, processed, status, skip_reason) \
+             VALUES (?, ?, ?, ?, ?, TRUE, 'skipped', ?)",
+        )
+        .bind(skipped.event_type)
+        .bind(skipped.sender.map_or("", |sender| sender.login.as_str()))
+        .bind(skipped.sender.map(|sender| sender.avatar_url.as_str()))
+        .bind(skipped.sender.map(|sender| sender.html_url.as_str()))
+        .bind(skipped.payload)
+        .bind(skipped.reason)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to record skipped event")?
+        .last_insert_rowid();
+
+        if let Some(delivery_id) = delivery_id {
+            Self::link_delivery(&mut tx, delivery_id, event_id).await?;
+        }
+        tx.commit().await?;
+        Ok(Enqueued::Created(event_id))
+    }
+
+    /// Prunes expired delivery GUIDs and records this one. Returns the earlier
+    /// delivery if the GUID was seen within the retention window.
+    async fn record_delivery(
+        tx: &mut Transaction<'_, Sqlite>,
+        delivery_id: &str,
+        retention_secs: u64,
+    ) -> Result<Option<Enqueued>> {
+        sqlx::query(
+            "DELETE FROM webhook_deliveries \
+             WHERE received_at < datetime('now', '-' || ? || ' seconds')",
+        )
+        .bind(retention_secs as i64)
+        .execute(&mut **tx)
+        .await
+        .context("Failed to prune webhook deliveries")?;
+
+        let recorded = sqlx::query(
+            "INSERT INTO webhook_deliveries (delivery_id) VALUES (?) \
+             ON CONFLICT (delivery_id) DO NOTHING",
+        )
+        .bind(delivery_id)
+        .execute(&mut **tx)
+        .await
+        .context("Failed to record webhook delivery")?
+        .rows_affected();
+        if recorded == 1 {
+            return Ok(None);
+        }
+
+        let event_id: Option<i64> =
+            sqlx::query_scalar("SELECT event_id FROM webhook_deliveries WHERE delivery_id = ?")
+                .bind(delivery_id)
+                .fetch_one(&mut **tx)
+                .await
+                .context("Failed to look up webhook delivery")?;
+        Ok(Some(Enqueued::Duplicate(event_id)))
+    }
+
+    async fn link_delivery(
+        tx: &mut Transaction<'_, Sqlite>,
+        delivery_id: &str,
+        event_id: i64,
+    ) -> Result<()> {
+        sqlx::query("UPDATE webhook_deliveries SET event_id = ? WHERE delivery_id = ?")
+            .bind(event_id)
+            .bind(delivery_id)
+            .execute(&mut **tx)
+            .await
+            .context("Failed to link webhook delivery")?;
+        Ok(())
+    }
+
+    async fn insert_event(
+        tx: &mut Transaction<'_, Sqlite>,
+        event: &GitHubEvent,
+        channels: &[String],
+    ) -> Result<i64> {
+        let payload = serde_json::to_string(event)?;
+
+        let event_id = sqlx::query(
+            "INSERT INTO notification_events \
+             (event_type, sender_login, sender_avatar_url, sender_html_url, payload, processed) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(event.key())
+        .bind(&event.sender().login)
+        .bind(&event.sender().avatar_url)
+        .bind(&event.sender().html_url)
+        .bind(payload)
+        .bind(channels.is_empty())
+        .execute(&mut **tx)
+        .await
+        .context("Failed to record event")?
+        .last_insert_rowid();
+
+        for channel in channels {
+            sqlx::query(
+                "INSERT INTO service_notifications (event_id, service_type, status) VALUES (?, ?, ?)",
+            )
+            .bind(event_id)
+            .bind(channel)
+            .bind(DeliveryStatus::Pending.as_str())
+            .execute(&mut **tx)
+            .await
+            .context("Failed to record pending delivery")?;
+        }
+
+        Ok(event_id)
+    }
+
+    /// Deliveries whose next attempt is due, optionally limited to one event.
+    pub async fn due_notifications(
+        &self,
+        event_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<PendingNotification>> {
+        sqlx::query_as(
+            "SELECT notification_id, event_id, service_type, attempts, payload, \
+                 CAST(strftime('%s', created_at) AS INTEGER) AS received_at \
+             FROM v_pending_notifications \
+             WHERE (next_attempt_at IS NULL OR next_attempt_at <= CURRENT_TIMESTAMP) \
+             AND (?1 IS NULL OR event_id = ?1) \
+             ORDER BY notification_id \
+             LIMIT ?2",
+        )
+        .bind(event_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to load pending deliveries")
+    }
+
+    /// Takes a lease on a due delivery and counts the attempt. Returns `false`
+    /// when another worker got there first. If the process dies mid-delivery
+    /// the lease simply expires and the row becomes due again.
+    pub async fn claim(&self, notification_id: i64, lease_secs: u64) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE service_notifications \
+             SET status = ?, \
+                 attempts = attempts + 1, \
+                 last_attempt_at = CURRENT_TIMESTAMP, \
+                 next_attempt_at = datetime('now', '+' || ? || ' seconds') \
+             WHERE id = ? \
+             AND status IN ('pending', 'failed', 'in_flight') \
+             AND (next_attempt_at IS NULL OR next_attempt_at <= CURRENT_TIMESTAMP)",
+        )
+        .bind(DeliveryStatus::InFlight.as_str())
+        .bind(lease_secs as i64)
+        .bind(notification_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to claim delivery")?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn mark_sent(&self, notification_id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE service_notifications \
+             SET status = ?, sent_at = CURRENT_TIMESTAMP, next_attempt_at = NULL, error_message = NULL \
+             WHERE id = ? AND status = 'in_flight'",
+        )
+        .bind(DeliveryStatus::Sent.as_str())
+        .bind(notification_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark delivery as sent")?;
+
+        Ok(())
+    }
+
+    /// Records a failed attempt and schedules the next one `retry_in_secs` from now.
+    pub async fn mark_failed(
+        &self,
+        notification_id: i64,
+        error: &str,
+        retry_in_secs: u64,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE service_notifications \
+             SET status = ?, error_message = ?, next_attempt_at = datetime('now', '+' || ? || ' seconds') \
+             WHERE id = ? AND status = 'in_flight'",
+        )
+        .bind(DeliveryStatus::Failed.as_str())
+        .bind(error)
+        .bind(retry_in_secs as i64)
+        .bind(notification_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark delivery as failed")?;
+
+        Ok(())
+    }
+
+    /// Pushes a delivery back without using up an attempt, for when the
+    /// channel asked us to slow down.
+    pub async fn postpone(&self, notification_id: i64, retry_in_secs: u64) -> Result<()> {
+        // back to 'failed' if an earlier attempt had failed, else 'pending'
+        sqlx::query(
+            "UPDATE service_notifications \
+             SET status = CASE WHEN attempts > 1 THEN 'failed' ELSE 'pending' END, \
+                 attempts = MAX(attempts - 1, 0), \
+                 next_attempt_at = datetime('now', '+' || ? || ' seconds') \
+             WHERE id = ? AND status = 'in_flight'",
+        )
+        .bind(retry_in_secs as i64)
+        .bind(notification_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to postpone delivery")?;
+
+        Ok(())
+    }
+
+    pub async fn mark_dead(&self, notification_id: i64, error: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE service_notifications \
+             SET status = ?, error_message = ?, next_attempt_at = NULL \
+             WHERE id = ? AND status = 'in_flight'",
+        )
+        .bind(DeliveryStatus::Dead.as_str())
+        .bind(error)
+        .bind(notification_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark delivery as dead")?;
+
+        Ok(())
+    }
+
+    /// Flags an event as processed once none of its deliveries are outstanding.
+    pub async fn complete_event(&self, event_id: i64) -> Result<()> {
+        sqlx::query(
+            "UPDATE notification_events SET processed = TRUE \
+             WHERE id = ?1 \
+             AND NOT EXISTS ( \
+                 SELECT 1 FROM service_notifications \
+                 WHERE event_id = ?1 AND status IN ('pending', 'failed', 'in_flight') \
+             )",
+        )
+        .bind(event_id)
+        .execute(&self.pool)
+        .await
+        .context("Failed to mark event as processed")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Database {
+    /// A fresh database in the temp dir, for tests. Remove the file when done.
+    pub(crate) async fn temporary(name: &str) -> (Self, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "github-notification-service-{}-{}.sqlite",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db = Database::connect(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+        (db, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FollowerEvent;
+
+    fn followed(login: &str) -> GitHubEvent {
+        GitHubEvent::Follower(FollowerEvent {
+            action: "followed".to_string(),
+            sender: Sender {
+                login: login.to_string(),
+                avatar_url: String::new(),
+                html_url: String::new(),
+            },
+            followed_at: None,
+        })
+    }
+
+    async fn count(db: &Database, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&db.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn redelivered_webhook_is_enqueued_once() {
+        let (db, path) = Database::temporary("dedup").await;
+        let channels = vec!["slack".to_string(), "discord".to_string()];
+        let event = followed("octocat");
+
+        let first = db
+            .enqueue_webhook("delivery-1", 3600, &event, &channels)
+            .await
+            .unwrap();
+        let Enqueued::Created(event_id) = first else {
+            panic!("first delivery was not enqueued: {:?}", first);
+        };
+
+        let again = db
+            .enqueue_webhook("delivery-1", 3600, &event, &channels)
+            .await
+            .unwrap();
+        assert_eq!(again, Enqueued::Duplicate(Some(event_id)));
+        assert_eq!(count(&db, "notification_events").await, 1);
+        assert_eq!(count(&db, "service_notifications").await, 2);
+
+        // a different delivery of the same payload is a new event
+        let other = db
+            .enqueue_webhook("delivery-2", 3600, &event, &channels)
+            .await
+            .unwrap();
+        assert!(matches!(other, Enqueued::Created(id) if id != event_id));
+        assert_eq!(count(&db, "service_notifications").await, 4);
+
+        db.pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn skipped_webhooks_are_kept_in_the_history() {
+        let (db, path) = Database::temporary("skipped").await;
+        let skipped = SkippedEvent {
+            event_type: "label",
+            sender: None,
+            payload: "{}",
+            reason: "unsupported event",
+        };
+
+        let first = db
+            .record_skipped(&skipped, Some("delivery-1"), 3600)
+            .await
+            .unwrap();
+        let Enqueued::Created(event_id) = first else {
+            panic!("skipped webhook was not recorded: {:?}", first);
+        };
+        let again = db
+            .record_skipped(&skipped, Some("delivery-1"), 3600)
+            .await
+            .unwrap();
+        assert_eq!(again, Enqueued::Duplicate(Some(event_id)));
+
+        let record = db.event(event_id).await.unwrap().unwrap();
+        assert_eq!(record.status, "skipped");
+        assert_eq!(record.skip_reason.as_deref(), Some("unsupported event"));
+        assert!(record.processed);
+        assert_eq!(count(&db, "service_notifications").await, 0);
+
+        db.pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,498 @@
+use anyhow::{anyhow, bail, Context, Result};
+use minijinja::{Environment, UndefinedBehavior};
+use serde::Serialize;
+use serde_json::json;
+use std::{collections::BTreeMap, path::Path};
+use tracing::info;
+
+use crate::{
+    models::{Actor, GitHubEvent, Notification},
+    services::{ChannelInfo, ChannelKind},
+    util::unix_now,
+};
+
+/// Events we know how to announce, with their built-in title and body.
+const BUILTIN_TEMPLATES: &[(&str, &str, &str)] = &[
+    (
+        "followed",
+        "New GitHub Follower!",
+        "User {{ sender.login }} is now following you!",
+    ),
+    (
+        "unfollowed",
+        "GitHub Follower Lost",
+        "User {{ sender.login }} stopped following you\
+         {% if followed_for %} after {{ followed_for }}{% endif %}.",
+    ),
+    (
+        "star",
+        "New GitHub Star!",
+        "{{ sender.login }} starred {{ repository.full_name }}\
+         {% if repository.stargazers_count %}, now at {{ repository.stargazers_count }} stars{% endif %}.",
+    ),
+    (
+        "fork",
+        "Repository Forked",
+        "{{ sender.login }} forked {{ repository.full_name }} to {{ forkee.full_name }}.",
+    ),
+    (
+        "watch",
+        "New Watcher",
+        "{{ sender.login }} is now watching {{ repository.full_name }}.",
+    ),
+    (
+        "issues",
+        "Issue {{ action }} in {{ repository.full_name }}",
+        "{{ sender.login }} {{ action }} issue #{{ issue.number }}: {{ issue.title }}",
+    ),
+    (
+        "pull_request",
+        "Pull request {% if pull_request.merged %}merged{% else %}{{ action }}{% endif %} \
+         in {{ repository.full_name }}",
+        "{{ sender.login }} {% if pull_request.merged %}merged{% else %}{{ action }}{% endif %} \
+         pull request #{{ pull_request.number }}: {{ pull_request.title }}",
+    ),
+    (
+        "release",
+        "New Release: {{ repository.full_name }} {{ release.tag_name }}",
+        "{{ sender.login }} published {{ release.name or release.tag_name }}\
+         {% if release.prerelease %} (pre-release){% endif %}.",
+    ),
+    (
+        "sponsorship",
+        "{% if action == 'created' %}New GitHub Sponsor!{% else %}GitHub Sponsorship Changed{% endif %}",
+        "{{ sponsorship.sponsor.login }} \
+         {% if action == 'created' %}started sponsoring you\
+         {% elif action == 'cancelled' %}cancelled their sponsorship\
+         {% else %}changed their sponsorship{% endif %} ({{ sponsorship.tier.name }}).",
+    ),
+    (
+        "public",
+        "Repository Made Public",
+        "{{ repository.full_name }} was made public by {{ sender.login }}.",
+    ),
+    (
+        "member",
+        "Collaborator {{ action }}",
+        "{{ sender.login }} {{ action }} {{ member.login }} as a collaborator on \
+         {{ repository.full_name }}.",
+    ),
+];
+
+const PARTS: [&str; 2] = ["title", "body"];
+const TEMPLATE_EXTENSION: &str = "j2";
+
+/// Renders notifications from minijinja templates named `<event>.<part>`,
+/// with optional per-channel overrides named `<event>.<channel>.<part>`,
+/// where `<channel>` is either a channel name or a channel kind.
+pub struct TemplateRenderer {
+    env: Environment<'static>,
+}
+
+/// The event's own fields (`action`, `sender`, `repository`, ...) plus the
+/// extras below.
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    #[serde(flatten)]
+    event: &'a GitHubEvent,
+    /// How long an unfollower had been following, e.g. "3 days".
+    followed_for: Option<String>,
+    channel: Option<&'a str>,
+    channel_kind: Option<&'static str>,
+}
+
+impl TemplateRenderer {
+    /// Loads the built-in templates, then `*.j2` files from `dir`, then inline
+    /// `overrides`, later ones replacing earlier ones. Every template is
+    /// test-rendered so mistakes surface at startup rather than at delivery.
+    /// `channels` are the configured channel names a template may target.
+    pub fn new(
+        dir: Option<&Path>,
+        overrides: &BTreeMap<String, String>,
+        channels: &[&str],
+    ) -> Result<Self> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+
+        for (event, title, body) in BUILTIN_TEMPLATES {
+            env.add_template_owned(format!("{}.title", event), *title)?;
+            env.add_template_owned(format!("{}.body", event), *body)?;
+        }
+
+        let mut sources = Vec::new();
+        if let Some(dir) = dir {
+            sources.extend(Self::read_dir(dir)?);
+        }
+        sources.extend(overrides.iter().map(|(n, s)| (n.clone(), s.clone())));
+
+        for (name, source) in sources {
+            Self::validate_name(&name, channels)?;
+            env.add_template_owned(name.clone(), source)
+                .with_context(|| format!("Invalid template {}", name))?;
+            info!("Loaded notification template {}", name);
+        }
+
+        let renderer = Self { env };
+        renderer.validate()?;
+        Ok(renderer)
+    }
+
+    fn read_dir(dir: &Path) -> Result<Vec<(String, String)>> {
+        let entries = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read template directory {}", dir.display()))?;
+
+        let mut templates = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(TEMPLATE_EXTENSION) {
+                continue;
+            }
+            let name = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| anyhow!("Invalid template file name {}", path.display()))?
+                .to_string();
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read template {}", path.display()))?;
+            templates.push((name, source));
+        }
+        Ok(templates)
+    }
+
+    fn validate_name(name: &str, channels: &[&str]) -> Result<()> {
+        let segments: Vec<&str> = name.split('.').collect();
+        let (event, channel, part) = match segments.as_slice() {
+            [event, part] => (*event, None, *part),
+            [event, channel, part] => (*event, Some(*channel), *part),
+            _ => bail!(
+                "Template {} must be named <event>.<part> or <event>.<channel>.<part>",
+                name
+            ),
+        };
+
+        if !Self::is_known_event(event) {
+            bail!("Template {} refers to unknown event {}", name, event);
+        }
+        if !PARTS.contains(&part) {
+            bail!("Template {} must end in one of {:?}", name, PARTS);
+        }
+        // a misspelt channel would otherwise never be picked
+        if let Some(channel) = channel {
+            let known = channels.contains(&channel)
+                || ChannelKind::ALL.iter().any(|kind| kind.as_str() == channel);
+            if !known {
+                bail!(
+                    "Template {} refers to unknown channel {}, expected a channel name or kind",
+                    name,
+                    channel
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders every template against a sample event to catch unknown
+    /// variables and runtime errors.
+    fn validate(&self) -> Result<()> {
+        for (name, template) in self.env.templates() {
+            let event = sample_event(name.split('.').next().unwrap_or_default())?;
+            let context = TemplateContext {
+                event: &event,
+                followed_for: Some("3 days".to_string()),
+                channel: Some("sample"),
+                channel_kind: Some("slack"),
+            };
+            template
+                .render(&context)
+                .with_context(|| format!("Template {} failed to render", name))?;
+        }
+        Ok(())
+    }
+
+    fn is_known_event(key: &str) -> bool {
+        Self::events().any(|event| event == key)
+    }
+
+    /// Keys of the events templates can be written for.
+    pub fn events() -> impl Iterator<Item = &'static str> {
+        BUILTIN_TEMPLATES.iter().map(|(event, _, _)| *event)
+    }
+
+    pub fn supports(&self, event: &GitHubEvent) -> bool {
+        Self::is_known_event(event.key())
+    }
+
+    /// Builds the notification for an event as it should look on `channel`,
+    /// or in general when no channel is given.
+    pub fn render(
+        &self,
+        event: &GitHubEvent,
+        channel: Option<&ChannelInfo>,
+    ) -> Result<Notification> {
+        let key = event.key();
+        if !self.supports(event) {
+            bail!("Unsupported event: {}", key);
+        }
+
+        let sender = event.sender();
+        let context = TemplateContext {
+            event,
+            followed_for: event
+                .followed_at()
+                .map(|followed_at| format_follow_duration(unix_now().saturating_sub(followed_at))),
+            channel: channel.map(|c| c.name.as_str()),
+            channel_kind: channel.map(|c| c.kind.as_str()),
+        };
+
+        let mut notification = Notification {
+            title: self.render_part(key, "title", channel, &context)?,
+            body: self.render_part(key, "body", channel, &context)?,
+            link: Some(event.link().to_string()),
+            image: Some(sender.avatar_url.clone()).filter(|url| !url.is_empty()),
+            actor: Some(Actor {
+                login: sender.login.clone(),
+                html_url: sender.html_url.clone(),
+                avatar_url: Some(sender.avatar_url.clone()),
+            }),
+            fields: Vec::new(),
+        };
+        for (name, value) in fields(event) {
+            notification = notification.field(name, value);
+        }
+        Ok(notification)
+    }
+
+    fn render_part(
+        &self,
+        event: &str,
+        part: &str,
+        channel: Option<&ChannelInfo>,
+        context: &TemplateContext,
+    ) -> Result<String> {
+        let mut candidates = Vec::new();
+        if let Some(channel) = channel {
+            candidates.push(format!("{}.{}.{}", event, channel.name, part));
+            candidates.push(format!("{}.{}.{}", event, channel.kind, part));
+        }
+        candidates.push(format!("{}.{}", event, part));
+
+        let (name, template) = candidates
+            .iter()
+            .find_map(|name| self.env.get_template(name).ok().map(|t| (name, t)))
+            .ok_or_else(|| anyhow!("No {} template for {}", part, event))?;
+
+        let rendered = template
+            .render(context)
+            .with_context(|| format!("Template {} failed to render", name))?;
+        Ok(rendered.trim().to_string())
+    }
+}
+
+/// Facts shown next to the message, e.g. the repository or issue number.
+fn fields(event: &GitHubEvent) -> Vec<(&'static str, String)> {
+    match event {
+        GitHubEvent::Follower(e) => vec![("Profile", e.sender.html_url.clone())],
+        GitHubEvent::Star(e) => vec![("Repository", e.repository.full_name.clone())],
+        GitHubEvent::Fork(e) => vec![
+            ("Repository", e.repository.full_name.clone()),
+            ("Fork", e.forkee.full_name.clone()),
+        ],
+        GitHubEvent::Watch(e) => vec![("Repository", e.repository.full_name.clone())],
+        GitHubEvent::Issues(e) => vec![
+            ("Repository", e.repository.full_name.clone()),
+            ("Issue", format!("#{}", e.issue.number)),
+        ],
+        GitHubEvent::PullRequest(e) => vec![
+            ("Repository", e.repository.full_name.clone()),
+            ("Pull request", format!("#{}", e.pull_request.number)),
+        ],
+        GitHubEvent::Release(e) => vec![
+            ("Repository", e.repository.full_name.clone()),
+            ("Tag", e.release.tag_name.clone()),
+        ],
+        GitHubEvent::Sponsorship(e) => vec![("Tier", e.sponsorship.tier.name.clone())],
+        GitHubEvent::Public(e) => vec![("Repository", e.repository.full_name.clone())],
+        GitHubEvent::Member(e) => vec![
+            ("Repository", e.repository.full_name.clone()),
+            ("Collaborator", e.member.login.clone()),
+        ],
+    }
+}
+
+/// An event of the kind a template is named after, to test-render it with.
+fn sample_event(key: &str) -> Result<GitHubEvent> {
+    let user = json!({
+        "login": "octocat",
+        "avatar_url": "https://github.com/images/error/octocat_happy.gif",
+        "html_url": "https://github.com/octocat",
+    });
+    let repository = json!({
+        "full_name": "octocat/Hello-World",
+        "html_url": "https://github.com/octocat/Hello-World",
+        "description": "My first repository",
+        "stargazers_count": 80,
+    });
+
+    let (event, payload) = match key {
+        "followed" | "unfollowed" => (None, json!({ "action": key, "sender": user })),
+        event => {
+            let action = match event {
+                "star" => "created",
+                "watch" => "started",
+                "release" => "published",
+                "member" => "added",
+                "sponsorship" => "created",
+                _ => "opened",
+            };
+            let payload = json!({
+                "action": action,
+                "sender": user,
+                "repository": repository,
+                "forkee": repository,
+                "issue": {
+                    "number": 1347,
+                    "title": "Found a bug",
+                    "html_url": "https://github.com/octocat/Hello-World/issues/1347",
+                    "user": user,
+                },
+                "pull_request": {
+                    "number": 1348,
+                    "title": "Fix the bug",
+                    "html_url": "https://github.com/octocat/Hello-World/pull/1348",
+                    "user": user,
+                },
+                "release": {
+                    "tag_name": "v1.0.0",
+                    "name": "v1.0.0",
+                    "html_url": "https://github.com/octocat/Hello-World/releases/tag/v1.0.0",
+                },
+                "sponsorship": {
+                    "sponsor": user,
+                    "tier": { "name": "$5 a month", "monthly_price_in_dollars": 5 },
+                },
+                "member": user,
+            });
+            (Some(event), payload)
+        }
+    };
+
+    GitHubEvent::parse(event, payload.to_string().as_bytes())?
+        .ok_or_else(|| anyhow!("No sample for event {}", key))
+}
+
+/// Renders a follow duration in the largest whole unit, e.g. "3 days".
+fn format_follow_duration(secs: u64) -> String {
+    const UNITS: [(u64, &str); 5] = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    UNITS
+        .iter()
+        .find(|(unit, _)| secs >= *unit)
+        .map(|(unit, name)| {
+            let count = secs / unit;
+            format!("{} {}{}", count, name, if count == 1 { "" } else { "s" })
+        })
+        .unwrap_or_else(|| "less than a minute".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer(overrides: &[(&str, &str)]) -> Result<TemplateRenderer> {
+        let overrides = overrides
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect();
+        TemplateRenderer::new(None, &overrides, &["slack-ops"])
+    }
+
+    fn channel(name: &str, kind: ChannelKind) -> ChannelInfo {
+        ChannelInfo {
+            name: name.to_string(),
+            kind,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn names_must_be_event_channel_and_part() {
+        let channels = ["slack-ops"];
+        assert!(TemplateRenderer::validate_name("followed.title", &channels).is_ok());
+        assert!(TemplateRenderer::validate_name("followed.slack-ops.body", &channels).is_ok());
+        assert!(TemplateRenderer::validate_name("pull_request.discord.title", &channels).is_ok());
+
+        for name in [
+            "followed",
+            "followed.slack.extra.body",
+            "stared.title",
+            "followed.subject",
+            "followed.slack-opps.body",
+        ] {
+            assert!(
+                TemplateRenderer::validate_name(name, &channels).is_err(),
+                "{} was accepted",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn channel_name_wins_over_kind_over_default() {
+        let renderer = renderer(&[
+            ("followed.slack-ops.title", "by name"),
+            ("followed.slack.title", "by kind"),
+        ])
+        .unwrap();
+        let event = sample_event("followed").unwrap();
+        let title =
+            |channel: Option<ChannelInfo>| renderer.render(&event, channel.as_ref()).unwrap().title;
+
+        assert_eq!(
+            title(Some(channel("slack-ops", ChannelKind::Slack))),
+            "by name"
+        );
+        assert_eq!(title(Some(channel("slack", ChannelKind::Slack))), "by kind");
+        assert_eq!(
+            title(Some(channel("discord", ChannelKind::Discord))),
+            "New GitHub Follower!"
+        );
+        assert_eq!(title(None), "New GitHub Follower!");
+    }
+
+    #[test]
+    fn templates_are_test_rendered_when_loaded() {
+        let error = renderer(&[("star.body", "{{ repository.no_such_field.name }}")])
+            .err()
+            .unwrap();
+        assert!(format!("{:#}", error).contains("star.body failed to render"));
+
+        assert!(renderer(&[("star.body", "{{ repository.full_name }} {{ channel }}")]).is_ok());
+    }
+
+    #[test]
+    fn unfollow_without_a_follow_date_leaves_the_duration_out() {
+        let renderer = renderer(&[]).unwrap();
+        let event = sample_event("unfollowed").unwrap();
+        assert_eq!(
+            renderer.render(&event, None).unwrap().body,
+            "User octocat stopped following you."
+        );
+    }
+
+    #[test]
+    fn follow_durations_use_the_largest_whole_unit() {
+        assert_eq!(format_follow_duration(0), "less than a minute");
+        assert_eq!(format_follow_duration(59), "less than a minute");
+        assert_eq!(format_follow_duration(60), "1 minute");
+        assert_eq!(format_follow_duration(2 * 60 * 60 + 59), "2 hours");
+        assert_eq!(format_follow_duration(3 * 24 * 60 * 60), "3 days");
+        assert_eq!(format_follow_duration(45 * 24 * 60 * 60), "1 month");
+        assert_eq!(format_follow_duration(800 * 24 * 60 * 60), "2 years");
+    }
+}